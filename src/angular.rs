@@ -0,0 +1,46 @@
+//! Detecting Angular apps.
+//!
+//! Apps built with the webpack based builder load their lazy routes through
+//! the webpack runtime in `runtime.<hash>.js`, which the client follows like
+//! any other, see [`find_runtime`](crate::webpack::find_runtime). The
+//! esbuild based builder of Angular 17 emits ES modules instead, which are
+//! followed through their imports, see [`crate::esm`].
+
+use async_trait::async_trait;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{find_attribute, Detection, FrameworkDetector, Page};
+
+/// Detects Angular apps. Their chunks are not listed anywhere but in the
+/// runtime, so the detection has none of its own.
+///
+/// The version is read from the `ng-version` attribute of prerendered pages.
+pub struct AngularDetector;
+
+#[async_trait]
+impl FrameworkDetector for AngularDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        _client: &ParsesmClient,
+        _errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        let version = find_attribute(page.body, "ng-version").map(str::to_owned);
+        let has_script = |prefix: &str| {
+            page.scripts.iter().find(|s| {
+                let name = s
+                    .path_segments()
+                    .and_then(|mut p| p.next_back())
+                    .unwrap_or_default();
+                name.starts_with(prefix) && name.ends_with(".js")
+            })
+        };
+        let builder_output = has_script("polyfills").is_some() && has_script("main").is_some();
+        if version.is_none() && !page.body.contains("<app-root") && !builder_output {
+            return None;
+        }
+
+        Some(Detection::new("angular").with_version(version))
+    }
+}
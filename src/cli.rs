@@ -0,0 +1,356 @@
+//! Command line interface definition.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use parsesm::events::EventFormat;
+use parsesm::limiter::{Limits, MAX_INTERVAL};
+use parsesm::report::EXIT_CODES_HELP;
+use parsesm::{
+    ArchiveFormat, ConflictPolicy, Fetcher, Headers, HttpFetcher, LocalFetcher, ParsesmClient,
+    ParsesmClientBuilder, ParsesmError, Scope, WarcWriter, DEFAULT_FOLLOW_DEPTH,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "parsesm",
+    version,
+    about = "Recover original sources from the sourcemaps a site serves",
+    after_help = EXIT_CODES_HELP
+)]
+pub struct Cli {
+    /// Format of the results printed to stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    pub output_format: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable progress on stderr
+    Text,
+    /// A single json document once the command finishes
+    Json,
+    /// One json event per line as the command runs
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn event_format(self) -> EventFormat {
+        match self {
+            OutputFormat::Text => EventFormat::Text,
+            OutputFormat::Json => EventFormat::Json,
+            OutputFormat::Ndjson => EventFormat::Ndjson,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Find the sourcemaps of a page and write the original sources to disk
+    Extract(ExtractArgs),
+    /// Extract many targets read from a file or stdin, one per line
+    Batch(BatchArgs),
+    /// Extract sourcemaps from local map files, scripts or directories
+    Offline(OfflineArgs),
+    /// Extract sourcemaps from traffic captured in a HAR file
+    Har(ReplayArgs),
+    /// Extract sourcemaps from responses archived in a WARC file
+    Warc(ReplayArgs),
+    /// List the sourcemaps of a page without writing anything
+    Analyze(AnalyzeArgs),
+    /// Map a position in generated code back to its original source
+    Lookup(LookupArgs),
+    /// Check that sourcemaps decode and summarize what they contain
+    Validate(ValidateArgs),
+    /// Print a completion script for a shell
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    /// Url of the page to extract sourcemaps from
+    pub url: String,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct OfflineArgs {
+    /// Map files, scripts with inline or adjacent maps, or directories to walk
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// Capture to replay, e.g. exported from the browser devtools, a proxy or
+    /// a crawler
+    pub capture: PathBuf,
+
+    /// Page to start from, the first html document in the capture by default
+    #[arg(long)]
+    pub page: Option<String>,
+
+    /// Fetch maps and sources missing from the capture over the network
+    #[arg(long)]
+    pub fetch_missing: bool,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// File with one target per line, stdin when omitted or `-`
+    pub input: Option<PathBuf>,
+
+    /// Accept bare hostnames, trying https first and then http
+    #[arg(long)]
+    pub expand_hosts: bool,
+
+    /// Maximum number of targets processed at once
+    #[arg(long, default_value_t = 4)]
+    pub parallel_targets: usize,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Directory recovered sources are written under
+    #[arg(short, long, default_value = "out")]
+    pub out_dir: PathBuf,
+
+    /// What to do with sources written to an existing path: overwrite, skip or version
+    #[arg(long, default_value = "version", value_parser = str::parse::<ConflictPolicy>)]
+    pub on_conflict: ConflictPolicy,
+    /// Pack each target into a .tar.gz or .zip archive named after its host
+    #[arg(long, value_name = "FORMAT", value_parser = str::parse::<ArchiveFormat>)]
+    pub archive: Option<ArchiveFormat>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    /// Url of the page to analyze
+    pub url: String,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct LookupArgs {
+    /// Url or path of the sourcemap
+    pub map: String,
+
+    /// Line in the generated file, starting at 1
+    pub line: u32,
+
+    /// Column in the generated file, starting at 1
+    pub column: u32,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Urls or paths of the sourcemaps
+    #[arg(required = true)]
+    pub maps: Vec<String>,
+
+    #[command(flatten)]
+    pub network: NetworkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct NetworkArgs {
+    /// Scripts to follow: same-origin, same-site, any or allow:<host>,<host>
+    #[arg(long, default_value = "same-site", value_parser = str::parse::<Scope>)]
+    pub scope: Scope,
+
+    /// Extra request header as `Name: value`, may be repeated
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+
+    /// Maximum number of requests in flight
+    #[arg(long, default_value_t = Limits::default().concurrency)]
+    pub concurrency: usize,
+
+    /// Maximum number of requests in flight to a single host
+    #[arg(long, default_value_t = Limits::default().per_host)]
+    pub per_host: usize,
+
+    /// Maximum number of requests started per second
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: Option<f64>,
+
+    /// Timeout of a single request in seconds
+    #[arg(long, default_value_t = 30)]
+    pub timeout: u64,
+
+    /// Verify tls certificates and hostnames
+    #[arg(long)]
+    pub verify_tls: bool,
+
+    /// Drop the query string of a script when guessing its map url
+    #[arg(long)]
+    pub drop_query: bool,
+
+    /// How many rounds of scripts loaded by other scripts to follow
+    #[arg(long, default_value_t = DEFAULT_FOLLOW_DEPTH)]
+    pub follow_depth: usize,
+
+    /// Serve every request from a mirror laid out as <dir>/<host>/<path>
+    /// instead of the network
+    #[arg(long, value_name = "DIR")]
+    pub mirror: Option<PathBuf>,
+
+    /// Record every http request and response into a WARC file, compressed
+    /// when it ends in .gz
+    #[arg(long, value_name = "FILE")]
+    pub warc: Option<PathBuf>,
+
+    #[arg(skip)]
+    recorder: Option<Arc<WarcWriter>>,
+}
+
+impl NetworkArgs {
+    /// Configures a client by these arguments.
+    pub fn client(&self) -> ParsesmClientBuilder {
+        let mut limits = Limits::default();
+        limits.concurrency = self.concurrency;
+        limits.per_host = self.per_host;
+        limits.requests_per_second = self.rate;
+
+        ParsesmClient::builder()
+            .with_fetcher(self.fetcher())
+            .with_scope(self.scope.clone())
+            .with_keep_query(!self.drop_query)
+            .with_limits(limits)
+            .with_follow_depth(self.follow_depth)
+    }
+
+    /// Creates the fetcher for the network or the mirror these arguments
+    /// configure.
+    pub fn fetcher(&self) -> Arc<dyn Fetcher> {
+        match &self.mirror {
+            Some(dir) => Arc::new(LocalFetcher::new(dir)),
+            None => {
+                let mut headers = Headers::new();
+                for (name, value) in &self.headers {
+                    headers
+                        .append(name, value)
+                        .expect("headers are checked when parsed");
+                }
+                Arc::new(
+                    HttpFetcher::with_options(
+                        &headers,
+                        self.verify_tls,
+                        Duration::from_secs(self.timeout),
+                    )
+                    .with_recorder(self.recorder.clone()),
+                )
+            }
+        }
+    }
+
+    /// Creates the WARC file requested with `--warc`. Must be called once
+    /// before any fetcher is created so all of them share the file.
+    pub fn open_recorder(&mut self) -> Result<(), ParsesmError> {
+        if let Some(path) = &self.warc {
+            self.recorder = Some(Arc::new(WarcWriter::create(path)?));
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    /// Network arguments of the command, if it makes requests.
+    pub fn network_mut(&mut self) -> Option<&mut NetworkArgs> {
+        match self {
+            Command::Extract(args) => Some(&mut args.network),
+            Command::Batch(args) => Some(&mut args.network),
+            Command::Har(args) | Command::Warc(args) => Some(&mut args.network),
+            Command::Analyze(args) => Some(&mut args.network),
+            Command::Lookup(args) => Some(&mut args.network),
+            Command::Validate(args) => Some(&mut args.network),
+            Command::Offline(_) | Command::Completions { .. } => None,
+        }
+    }
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    let min = 1.0 / MAX_INTERVAL.as_secs_f64();
+    if !rate.is_finite() || rate < min {
+        return Err(format!(
+            "expected at least one request every {} seconds",
+            MAX_INTERVAL.as_secs()
+        ));
+    }
+    Ok(rate)
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid header `{}`, expected `Name: value`", s))?;
+    let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_owned());
+    Headers::new().append(&name, &value)?;
+    Ok((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_headers() {
+        let (name, value) = parse_header("X-Token:  abc ").unwrap();
+        assert_eq!(name, "x-token");
+        assert_eq!(value, "abc");
+        assert!(parse_header("no colon").is_err());
+    }
+
+    #[test]
+    fn rates_must_be_reachable() {
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("1e-300").is_err());
+        assert!(parse_rate("inf").is_err());
+        assert!(parse_rate("NaN").is_err());
+    }
+}
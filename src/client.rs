@@ -0,0 +1,1123 @@
+//! The http client driving discovery and extraction.
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ansi_term::Colour;
+use futures::future::join_all;
+use sourcemap::{decode, DecodedMap, RewriteOptions, SourceMap};
+use url::Url;
+
+use crate::data_url::{decode_data_url, is_data_url};
+use crate::discovery::{
+    document_base, find_inline_scripts, find_scripts, resolve_map_urls, resolve_source_url,
+};
+use crate::error::ParsesmError;
+use crate::events::{display_url, Event, Reporter};
+use crate::fetch::{Fetcher, Headers, HttpFetcher, Response};
+use crate::framework::{default_detectors, Detection, FrameworkDetector, Page};
+use crate::limiter::{Limits, RequestLimiter};
+use crate::literals::{find_script_references, resolve_literals};
+use crate::manifest::{sha256_hex, Manifest, ManifestFramework, ManifestScript, ManifestSource};
+use crate::normalize::SourceNormalizer;
+use crate::offline::discover_local;
+use crate::report::ExtractReport;
+use crate::scope::Scope;
+use crate::sink::{archive_name, ArchiveFormat, OutputDir, SinkFactory};
+use crate::webpack::{find_runtime, WebpackRuntime};
+use crate::writer::{ConflictPolicy, SourceWriter, WriteOutcome};
+
+/// How many times scripts loaded by other scripts, lazy webpack chunks,
+/// imported modules and scripts named in literals, are followed in turn.
+pub const DEFAULT_FOLLOW_DEPTH: usize = 5;
+
+/// Decodes a sourcemap, flattening index maps. Ram bundles are rejected.
+pub fn load_from_reader<R: Read>(mut rdr: R) -> Result<SourceMap, sourcemap::Error> {
+    match decode(&mut rdr) {
+        Ok(DecodedMap::Regular(sm)) => Ok(sm),
+        Ok(DecodedMap::Index(idx)) => idx.flatten_and_rewrite(&RewriteOptions {
+            load_local_source_contents: true,
+            ..Default::default()
+        }),
+        Ok(_) => Err(sourcemap::Error::IncompatibleSourceMap),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decodes a sourcemap into its source names and contents.
+///
+/// The decoded map is not `Send`, so only owned data is kept around.
+fn read_sources(body: &str) -> Result<Vec<(String, Option<String>)>, sourcemap::Error> {
+    let sm = load_from_reader(body.as_bytes())?;
+    Ok(sm
+        .sources()
+        .enumerate()
+        .map(|(idx, name)| {
+            let contents = sm.get_source_contents(idx as u32).map(str::to_owned);
+            (name.to_owned(), contents)
+        })
+        .collect())
+}
+
+/// Returns the `sourceRoot` of a sourcemap, which the decoder prefixes every
+/// source with but does not expose.
+fn read_source_root(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    value.get("sourceRoot")?.as_str().map(str::to_owned)
+}
+
+/// A script on a page and the sourcemap found for it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ScriptEntry {
+    /// Url of the script.
+    pub url: String,
+    /// Status of the script response, `None` when no response was received.
+    pub status: Option<u16>,
+    /// Url of the script after redirects.
+    pub final_url: Option<Url>,
+    /// Url of the map, the script url for inline maps.
+    pub map_url: Option<String>,
+    /// Status of the map response.
+    pub map_status: Option<u16>,
+    /// Whether the map was embedded in the script as a `data:` url.
+    pub inline_map: bool,
+    /// The map as json.
+    pub map_body: Option<String>,
+    /// Errors fetching the script or its map candidates. Candidates that
+    /// simply do not exist are not errors.
+    pub errors: Vec<ParsesmError>,
+    /// The webpack runtime in the script, if it has one.
+    pub webpack: Option<WebpackRuntime>,
+    /// Modules the script imports, if it is an ES module.
+    pub imports: Vec<Url>,
+    /// Script paths in the string literals of the script.
+    pub literals: Vec<String>,
+    /// Whether the script was only named in a string literal. Such paths may
+    /// not exist or be served the html of the app instead, so its errors are
+    /// reported but do not make the run partial.
+    pub guessed: bool,
+}
+
+impl ScriptEntry {
+    pub(crate) fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            status: None,
+            final_url: None,
+            map_url: None,
+            map_status: None,
+            inline_map: false,
+            map_body: None,
+            errors: vec![],
+            webpack: None,
+            imports: vec![],
+            literals: vec![],
+            guessed: false,
+        }
+    }
+}
+
+/// The scripts and sourcemaps found for a page.
+#[non_exhaustive]
+pub struct Discovery {
+    /// Url of the page after redirects.
+    pub page_url: Url,
+    /// Status of the page response, `None` for local input.
+    pub page_status: Option<u16>,
+    /// Scripts on the page that are in scope, in document order, followed by
+    /// the chunks of the app and the scripts they load.
+    pub scripts: Vec<ScriptEntry>,
+    /// Frameworks the page is built with.
+    pub frameworks: Vec<Detection>,
+    /// Errors of the page as a whole, such as framework manifests that could
+    /// not be fetched.
+    pub errors: Vec<ParsesmError>,
+}
+
+impl Discovery {
+    /// Returns `(map url, map body)` for every script with a map.
+    pub fn maps(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.scripts
+            .iter()
+            .filter_map(|s| Some((s.map_url.as_deref()?, s.map_body.as_deref()?)))
+    }
+}
+
+/// Extracts the original sources of a site from its sourcemaps.
+///
+/// Built with [`ParsesmClient::builder`], the defaults match the cli.
+pub struct ParsesmClient {
+    fetcher: Arc<dyn Fetcher>,
+    scope: Scope,
+    keep_query: bool,
+    sinks: Arc<dyn SinkFactory>,
+    conflict_policy: ConflictPolicy,
+    normalizer: SourceNormalizer,
+    limiter: RequestLimiter,
+    reporter: Arc<Reporter>,
+    detectors: Vec<Arc<dyn FrameworkDetector>>,
+    follow_depth: usize,
+}
+
+/// Configuration of a [`ParsesmClient`].
+pub struct ParsesmClientBuilder {
+    fetcher: Option<Arc<dyn Fetcher>>,
+    headers: Headers,
+    verify_tls: bool,
+    timeout: Duration,
+    scope: Scope,
+    keep_query: bool,
+    limits: Limits,
+    out_dir: PathBuf,
+    archive: Option<ArchiveFormat>,
+    sinks: Option<Arc<dyn SinkFactory>>,
+    conflict_policy: ConflictPolicy,
+    normalizer: SourceNormalizer,
+    reporter: Arc<Reporter>,
+    detectors: Vec<Arc<dyn FrameworkDetector>>,
+    follow_depth: usize,
+}
+
+impl Default for ParsesmClientBuilder {
+    fn default() -> Self {
+        Self {
+            fetcher: None,
+            headers: Headers::new(),
+            verify_tls: false,
+            timeout: Duration::from_secs(30),
+            scope: Scope::default(),
+            keep_query: true,
+            limits: Limits::default(),
+            out_dir: PathBuf::from("./out"),
+            archive: None,
+            sinks: None,
+            conflict_policy: ConflictPolicy::default(),
+            normalizer: SourceNormalizer::default(),
+            reporter: Arc::new(Reporter::default()),
+            detectors: default_detectors(),
+            follow_depth: DEFAULT_FOLLOW_DEPTH,
+        }
+    }
+}
+
+impl ParsesmClientBuilder {
+    /// Sets where everything is fetched from, an [`HttpFetcher`] configured
+    /// by the other settings by default.
+    ///
+    /// The headers, tls and timeout settings only apply to the default
+    /// fetcher.
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn Fetcher>) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    /// Sets headers sent with every request.
+    pub fn with_headers(mut self, headers: Headers) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets whether tls certificates and hostnames are verified. Off by default
+    /// since targets frequently serve broken certificates.
+    pub fn with_verify_tls(mut self, verify_tls: bool) -> Self {
+        self.verify_tls = verify_tls;
+        self
+    }
+
+    /// Sets the timeout of a single request, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets which scripts referenced by a page are followed.
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Sets whether guessed map urls keep the query string of their script.
+    pub fn with_keep_query(mut self, keep_query: bool) -> Self {
+        self.keep_query = keep_query;
+        self
+    }
+
+    /// Sets the concurrency and rate limits for requests.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the directory sources are written under, `./out` by default.
+    pub fn with_out_dir<P: Into<PathBuf>>(mut self, out_dir: P) -> Self {
+        self.out_dir = out_dir.into();
+        self
+    }
+
+    /// Sets whether each target is packed into an archive in the output
+    /// directory instead of a directory of its own.
+    pub fn with_archive(mut self, archive: Option<ArchiveFormat>) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    /// Sets where the output of each target is stored, an [`OutputDir`]
+    /// configured by the other settings by default.
+    ///
+    /// The output directory and archive settings only apply to the default.
+    pub fn with_sinks(mut self, sinks: Arc<dyn SinkFactory>) -> Self {
+        self.sinks = Some(sinks);
+        self
+    }
+
+    /// Sets how sources written to an existing path are handled.
+    pub fn with_conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Sets how source names are turned into output paths.
+    pub fn with_normalizer(mut self, normalizer: SourceNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Sets where progress events are reported.
+    pub fn with_reporter(mut self, reporter: Arc<Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Sets the detectors run on every page, all of
+    /// [`default_detectors`] by default.
+    pub fn with_detectors(mut self, detectors: Vec<Arc<dyn FrameworkDetector>>) -> Self {
+        self.detectors = detectors;
+        self
+    }
+
+    /// Sets how many rounds of scripts loaded by other scripts are followed,
+    /// [`DEFAULT_FOLLOW_DEPTH`] by default. Zero follows none.
+    pub fn with_follow_depth(mut self, follow_depth: usize) -> Self {
+        self.follow_depth = follow_depth;
+        self
+    }
+
+    /// Builds the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the default fetcher is used and the tls backend cannot be
+    /// initialized, like [`reqwest::Client::new`].
+    pub fn build(self) -> ParsesmClient {
+        let fetcher = self.fetcher.unwrap_or_else(|| {
+            Arc::new(HttpFetcher::with_options(
+                &self.headers,
+                self.verify_tls,
+                self.timeout,
+            ))
+        });
+
+        let sinks = self
+            .sinks
+            .unwrap_or_else(|| Arc::new(OutputDir::new(self.out_dir).with_archive(self.archive)));
+
+        ParsesmClient {
+            fetcher,
+            sinks,
+            scope: self.scope,
+            keep_query: self.keep_query,
+            conflict_policy: self.conflict_policy,
+            normalizer: self.normalizer,
+            limiter: RequestLimiter::new(self.limits),
+            reporter: self.reporter,
+            detectors: self.detectors,
+            follow_depth: self.follow_depth,
+        }
+    }
+}
+
+impl Default for ParsesmClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParsesmClient {
+    /// Creates a client with the default configuration.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Returns a builder for a client with the default configuration.
+    pub fn builder() -> ParsesmClientBuilder {
+        ParsesmClientBuilder::default()
+    }
+
+    /// The scripts followed from a page.
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// The reporter progress events are sent to.
+    pub fn reporter(&self) -> &Reporter {
+        &self.reporter
+    }
+
+    /// Fetches a page and the sourcemaps of the scripts it references.
+    ///
+    /// Fails only when the page itself cannot be fetched, errors for single
+    /// scripts are reported and kept on their [`ScriptEntry`].
+    pub async fn discover(&self, target: &str) -> Result<Discovery, ParsesmError> {
+        self.discover_with_scripts(target, &[]).await
+    }
+
+    /// Like [`ParsesmClient::discover`], also following `extra` scripts the
+    /// page does not reference itself, e.g. chunks seen in captured traffic.
+    ///
+    /// Scripts loaded by other scripts are followed as well: the chunks
+    /// webpack runtimes load lazily, the modules ES modules import and the
+    /// scripts named in string literals. So are the chunks the framework of
+    /// the page lists, see [`FrameworkDetector`].
+    pub async fn discover_with_scripts(
+        &self,
+        target: &str,
+        extra: &[Url],
+    ) -> Result<Discovery, ParsesmError> {
+        let Response {
+            final_url: page_url,
+            status: page_status,
+            body,
+            ..
+        } = self.fetch(target).await?;
+        self.reporter.emit(Event::PageFetched {
+            url: page_url.to_string(),
+            status: page_status,
+        });
+
+        let page_scripts = find_scripts(&page_url, &body);
+        let mut seen = HashSet::new();
+        let mut pending: Vec<String> = vec![];
+        for script in page_scripts.iter().chain(extra) {
+            if self.scope.allows(&page_url, script) && seen.insert(script.clone()) {
+                pending.push(script.to_string());
+            }
+        }
+        self.reporter.info(format!(
+            "found {} javascript files in scope",
+            Colour::White.bold().paint(pending.len().to_string())
+        ));
+
+        let scoped: Vec<Url> = page_scripts
+            .iter()
+            .filter(|s| self.scope.allows(&page_url, s))
+            .cloned()
+            .collect();
+        let page = Page::new(&page_url, &body, &scoped);
+        let mut frameworks = vec![];
+        let mut errors = vec![];
+        for detector in &self.detectors {
+            let start = errors.len();
+            let detection = detector.detect(&page, self, &mut errors).await;
+            for error in &errors[start..] {
+                self.reporter.error(error);
+            }
+            let detection = match detection {
+                Some(detection) => detection,
+                None => continue,
+            };
+            self.report_detection(&detection);
+            for chunk in &detection.chunks {
+                if self.scope.allows(&page_url, chunk) && seen.insert(chunk.clone()) {
+                    pending.push(chunk.to_string());
+                }
+            }
+            frameworks.push(detection);
+        }
+
+        let base = document_base(&page_url, &body);
+        let mut found: Vec<Url> = vec![];
+        // named in literals only, followed after the scripts found otherwise
+        let mut named: Vec<Url> = vec![];
+        for script in find_inline_scripts(&body) {
+            let runtime = find_runtime(&script);
+            if let Some(runtime) = &runtime {
+                found.extend(runtime.chunk_urls(&base, &base));
+            }
+            let (imports, literals) = find_script_references(&base, &script);
+            found.extend(imports);
+            let public_base = public_base(&base, runtime.as_ref());
+            // inline scripts include json data, which only the fallback scan reads
+            named.extend(resolve_literals(&literals.paths, &base, &public_base));
+        }
+        let mut guessed: HashSet<String> = HashSet::new();
+        let mut scripts = vec![];
+        for round in 0..=self.follow_depth {
+            for script in &pending {
+                self.reporter.emit(Event::ScriptFound {
+                    url: script.clone(),
+                });
+            }
+            let mut fetched = self.fetch_map_files(pending).await;
+            for script in &mut fetched {
+                script.guessed = guessed.contains(&script.url);
+                for error in &script.errors {
+                    self.reporter.error(error);
+                }
+                if let Some(url) = &script.final_url {
+                    if let Some(runtime) = &script.webpack {
+                        found.extend(runtime.chunk_urls(&base, url));
+                    }
+                    let public_base = public_base(&base, script.webpack.as_ref());
+                    named.extend(resolve_literals(&script.literals, url, &public_base));
+                }
+                found.extend(script.imports.iter().cloned());
+            }
+            scripts.extend(fetched);
+
+            if round == self.follow_depth {
+                break;
+            }
+            pending = found
+                .drain(..)
+                .filter(|url| self.scope.allows(&page_url, url) && seen.insert(url.clone()))
+                .map(String::from)
+                .collect();
+            for url in named.drain(..) {
+                if self.scope.allows(&page_url, &url) && seen.insert(url.clone()) {
+                    guessed.insert(url.to_string());
+                    pending.push(url.into());
+                }
+            }
+            if pending.is_empty() {
+                break;
+            }
+            self.reporter.info(format!(
+                "found {} lazy chunks and referenced scripts",
+                Colour::White.bold().paint(pending.len().to_string())
+            ));
+        }
+
+        Ok(Discovery {
+            page_url,
+            page_status: Some(page_status),
+            scripts,
+            frameworks,
+            errors,
+        })
+    }
+
+    /// Reports a detected framework and the routes it lists.
+    fn report_detection(&self, detection: &Detection) {
+        for (route, chunks) in &detection.routes {
+            self.reporter.emit(Event::RouteFound {
+                route: route.clone(),
+                chunks: chunks.iter().map(Url::to_string).collect(),
+            });
+        }
+        self.reporter.emit(Event::FrameworkDetected {
+            framework: detection.framework.clone(),
+            version: detection.version.clone(),
+            build: detection.build.clone(),
+            chunks: detection.chunks.len(),
+        });
+
+        let mut name = detection.framework.clone();
+        if let Some(version) = &detection.version {
+            name = format!("{} {}", name, version);
+        }
+        if let Some(build) = &detection.build {
+            name = format!("{} build {}", name, build);
+        }
+        self.reporter.info(format!(
+            "detected {} with {} routes and {} chunks",
+            Colour::White.bold().paint(name),
+            detection.routes.len(),
+            detection.chunks.len()
+        ));
+    }
+
+    /// Extracts the sourcemaps of a page into the output directory and writes
+    /// a manifest of everything fetched next to the sources.
+    pub async fn extract_map(&self, host: &str) -> ExtractReport {
+        self.extract_with_scripts(host, &[]).await
+    }
+
+    /// Like [`ParsesmClient::extract_map`], also following `extra` scripts
+    /// the page does not reference itself.
+    pub async fn extract_with_scripts(&self, host: &str, extra: &[Url]) -> ExtractReport {
+        let report = self.extract(host, extra).await;
+        self.reporter.emit(Event::Finished(report.clone()));
+        report
+    }
+
+    async fn extract(&self, host: &str, extra: &[Url]) -> ExtractReport {
+        let mut report = ExtractReport::new(host);
+        self.reporter.info(format!(
+            "attempting to find sourcemaps for {}",
+            Colour::White.bold().paint(host)
+        ));
+
+        let discovery = match self.discover_with_scripts(host, extra).await {
+            Ok(discovery) => discovery,
+            Err(e) => {
+                self.reporter.error(&e);
+                report.errors.record(&e);
+                report.page_error = Some(e.chain());
+                return report;
+            }
+        };
+        self.extract_discovery(host, discovery, report).await
+    }
+
+    /// Extracts the sourcemaps of local map files, scripts or directories the
+    /// same way [`ParsesmClient::extract_map`] does for a page, see
+    /// [`discover_local`].
+    ///
+    /// Sources without content are read from disk when they lie inside the
+    /// input. The output is named after the last component of `path`.
+    pub async fn extract_local(&self, path: &Path) -> ExtractReport {
+        let target = path.display().to_string();
+        let mut report = ExtractReport::new(&target);
+        self.reporter.info(format!(
+            "attempting to find sourcemaps in {}",
+            Colour::White.bold().paint(&target)
+        ));
+
+        let report = match discover_local(path) {
+            Ok(discovery) => {
+                for error in discovery.scripts.iter().flat_map(|s| &s.errors) {
+                    self.reporter.error(error);
+                }
+                self.extract_discovery(&target, discovery, report).await
+            }
+            Err(e) => {
+                self.reporter.error(&e);
+                report.errors.record(&e);
+                report.error = Some(e.chain());
+                report
+            }
+        };
+        self.reporter.emit(Event::Finished(report.clone()));
+        report
+    }
+
+    /// Writes the sources of every map in `discovery` and the manifest.
+    async fn extract_discovery(
+        &self,
+        target: &str,
+        discovery: Discovery,
+        mut report: ExtractReport,
+    ) -> ExtractReport {
+        let script_errors = discovery
+            .scripts
+            .iter()
+            .filter(|s| !s.guessed)
+            .flat_map(|s| &s.errors);
+        for error in discovery.errors.iter().chain(script_errors) {
+            report.errors.record(error);
+        }
+        let page_url = &discovery.page_url;
+        report.scripts = discovery.scripts.len();
+        report.maps = discovery.maps().count();
+
+        let mut manifest = Manifest::new(target);
+        manifest.page_url = Some(page_url.to_string());
+        manifest.page_status = discovery.page_status;
+        manifest.frameworks = discovery
+            .frameworks
+            .iter()
+            .map(|f| ManifestFramework {
+                name: f.framework.clone(),
+                version: f.version.clone(),
+                build: f.build.clone(),
+                routes: f.routes.keys().cloned().collect(),
+            })
+            .collect();
+        manifest.scripts = discovery
+            .scripts
+            .iter()
+            .map(|s| ManifestScript {
+                url: s.url.clone(),
+                status: s.status,
+                map_url: s.map_url.clone(),
+                map_status: s.map_status,
+                inline_map: s.inline_map,
+                map_sha256: s.map_body.as_ref().map(|b| sha256_hex(b.as_bytes())),
+            })
+            .collect();
+
+        // the manifest lists every script even when none has a map
+        let mut writer = match self.sinks.create(&output_name(target)) {
+            Ok(sink) => SourceWriter::new(sink, self.conflict_policy),
+            Err(e) => {
+                self.reporter.error(&e);
+                report.errors.record(&e);
+                report.error = Some(e.chain());
+                return report;
+            }
+        };
+
+        // needs to be string for colour
+        let scripts_len = report.scripts.to_string();
+        if report.maps == 0 {
+            self.reporter.info(format!(
+                "no sourcemaps found for {} javascript files. exiting",
+                Colour::White.bold().paint(&scripts_len)
+            ));
+            self.write_manifest(&manifest, writer, &mut report);
+            return report;
+        }
+
+        self.reporter.info(format!(
+            "found {}/{} sourcemaps for javascript files",
+            report.maps,
+            Colour::White.bold().paint(&scripts_len)
+        ));
+        for (map_url, map_body) in discovery.maps() {
+            let source_root = read_source_root(map_body);
+            let sources = match read_sources(map_body) {
+                Ok(sources) => sources,
+                Err(e) => {
+                    let e = ParsesmError::decode(&display_url(map_url), e);
+                    self.reporter.error(&e);
+                    report.errors.record(&e);
+                    report.invalid_maps += 1;
+                    continue;
+                }
+            };
+            self.reporter.emit(Event::MapLoaded {
+                url: display_url(map_url),
+                sources: sources.len(),
+                sha256: sha256_hex(map_body.as_bytes()),
+            });
+
+            // missing contents are downloaded concurrently up front so
+            // sources are still written in the order of the map
+            let missing = sources
+                .iter()
+                .filter(|(_, contents)| contents.is_none())
+                .map(|(name, _)| self.fetch_source(page_url, map_url, name));
+            let mut fetched = join_all(missing).await.into_iter();
+
+            for (name, contents) in sources {
+                let path = self
+                    .normalizer
+                    .normalize(&name, source_root.as_deref())
+                    .relative_path();
+
+                let contents = match contents {
+                    Some(contents) => contents,
+                    None => match fetched.next().flatten() {
+                        Some(Ok(contents)) => contents,
+                        Some(Err(e)) => {
+                            self.reporter.error(&e);
+                            report.errors.record(&e);
+                            report.unrecovered.push(name);
+                            continue;
+                        }
+                        None => {
+                            report.unrecovered.push(name);
+                            continue;
+                        }
+                    },
+                };
+
+                let sha256 = sha256_hex(contents.as_bytes());
+                let written_to = match writer.write(&path, &contents) {
+                    Ok(WriteOutcome::Written(path)) => {
+                        self.reporter.emit(Event::SourceWritten {
+                            name: name.clone(),
+                            path: format!("{}/{}", writer.location(), archive_name(&path)),
+                            size: contents.len(),
+                            sha256: sha256.clone(),
+                        });
+                        Some(path)
+                    }
+                    Ok(WriteOutcome::Duplicate(path)) => Some(path),
+                    Ok(WriteOutcome::Skipped) => None,
+                    Err(e) => {
+                        self.reporter.error(&e);
+                        report.errors.record(&e);
+                        report.write_errors += 1;
+                        None
+                    }
+                };
+                for warning in writer.take_warnings() {
+                    self.reporter.error(&warning);
+                    report.errors.record(&warning);
+                }
+
+                manifest.sources.push(ManifestSource {
+                    name,
+                    map_url: display_url(map_url),
+                    path: written_to.map(|p| archive_name(&p)),
+                    size: contents.len(),
+                    sha256,
+                });
+            }
+        }
+
+        if !report.unrecovered.is_empty() {
+            self.reporter.info(format!(
+                "{} {} sources without content could not be recovered",
+                Colour::Yellow.paint("warning:"),
+                report.unrecovered.len()
+            ));
+            for name in &report.unrecovered {
+                self.reporter.info(format!("  {}", name));
+            }
+        }
+
+        let stats = writer.stats();
+        report.written = stats.written;
+        report.duplicates = stats.duplicates;
+        report.conflicts = stats.conflicts;
+        self.reporter.info(format!(
+            "wrote {} sources, {} duplicates, {} conflicts",
+            Colour::White.bold().paint(stats.written.to_string()),
+            stats.duplicates,
+            stats.conflicts
+        ));
+
+        manifest.unrecovered = report.unrecovered.clone();
+        self.write_manifest(&manifest, writer, &mut report);
+        report
+    }
+
+    /// Writes the manifest of a target and finishes its sink.
+    fn write_manifest(
+        &self,
+        manifest: &Manifest,
+        writer: SourceWriter,
+        report: &mut ExtractReport,
+    ) {
+        let mut sink = writer.into_sink();
+        if let Err(e) = manifest.write(sink.as_mut()).and_then(|_| sink.finish()) {
+            self.reporter.error(&e);
+            report.errors.record(&e);
+            report.write_errors += 1;
+        }
+    }
+
+    /// Fetches every script and then the sourcemap it points to.
+    ///
+    /// Scripts are processed concurrently within the client's limits and are
+    /// returned in the order of `scripts`. Inline maps are decoded in place
+    /// and reported with the script url.
+    pub async fn fetch_map_files(&self, scripts: Vec<String>) -> Vec<ScriptEntry> {
+        join_all(scripts.iter().map(|s| self.fetch_map_file(s))).await
+    }
+
+    /// Fetches a script and then the first of its map candidates that exists.
+    async fn fetch_map_file(&self, script_url: &str) -> ScriptEntry {
+        let mut entry = ScriptEntry::new(script_url);
+        let script = match self.fetch(script_url).await {
+            Ok(script) => script,
+            Err(e) => {
+                entry.status = e.status();
+                entry.errors.push(e);
+                return entry;
+            }
+        };
+        entry.status = Some(script.status);
+        entry.final_url = Some(script.final_url.clone());
+        entry.webpack = find_runtime(&script.body);
+        let (imports, literals) = find_script_references(&script.final_url, &script.body);
+        entry.imports = imports;
+        entry.literals = literals.paths;
+        // other content, e.g. the html of an app answering any path, is not
+        // expected to parse
+        if let (Some(message), true) = (literals.parse_error, script.is_javascript()) {
+            entry.errors.push(ParsesmError::Parse {
+                url: script_url.to_owned(),
+                message,
+            });
+        }
+
+        // relative references in the script are relative to where it ended up
+        let candidates = resolve_map_urls(
+            script.final_url.as_str(),
+            &script.headers,
+            &script.body,
+            self.keep_query,
+        );
+        for map_url in candidates {
+            if is_data_url(&map_url) {
+                let decoded = decode_data_url(&map_url);
+                self.reporter.emit(Event::MapCandidateTried {
+                    script_url: script_url.to_owned(),
+                    map_url: display_url(&map_url),
+                    status: None,
+                    found: decoded.is_some(),
+                });
+                if let Some(map_body) = decoded {
+                    entry.map_url = Some(script_url.to_owned());
+                    entry.inline_map = true;
+                    entry.map_body = Some(map_body);
+                    return entry;
+                }
+                continue;
+            }
+
+            let map = self.fetch(&map_url).await;
+            self.reporter.emit(Event::MapCandidateTried {
+                script_url: script_url.to_owned(),
+                map_url: map_url.clone(),
+                status: map
+                    .as_ref()
+                    .map_or_else(ParsesmError::status, |m| Some(m.status)),
+                found: map.is_ok(),
+            });
+            match map {
+                Ok(map) => {
+                    entry.map_url = Some(map_url);
+                    entry.map_status = Some(map.status);
+                    entry.map_body = Some(map.body);
+                    return entry;
+                }
+                // most candidates are guesses so a missing one is expected
+                Err(ParsesmError::HttpStatus { .. }) => {}
+                Err(e) => entry.errors.push(e),
+            }
+        }
+
+        entry
+    }
+
+    /// Downloads a source that has no `sourcesContent` entry in its map.
+    ///
+    /// The source name already carries the map's `sourceRoot` and is resolved
+    /// against the map url. Only http(s) sources within scope are fetched,
+    /// `None` is returned for the others.
+    async fn fetch_source(
+        &self,
+        page_url: &Url,
+        map_url: &str,
+        source: &str,
+    ) -> Option<Result<String, ParsesmError>> {
+        let url = resolve_source_url(map_url, source)?;
+        if url.scheme() == "file" {
+            return read_local_source(page_url, &url).await;
+        }
+        if !self.scope.allows(page_url, &url) {
+            return None;
+        }
+
+        Some(self.fetch(url.as_str()).await.map(|f| f.body))
+    }
+
+    /// Fetches a url, failing for non 2xx responses.
+    pub async fn fetch(&self, url: &str) -> Result<Response, ParsesmError> {
+        let parsed = Url::parse(url).map_err(|source| ParsesmError::InvalidUrl {
+            url: url.to_owned(),
+            source,
+        })?;
+
+        let resp = {
+            let _permit = self
+                .limiter
+                .acquire(parsed.host_str().unwrap_or_default())
+                .await;
+            self.fetcher.get(&parsed).await?
+        };
+        if !resp.is_success() {
+            return Err(ParsesmError::HttpStatus {
+                url: url.to_owned(),
+                status: resp.status,
+            });
+        }
+
+        Ok(resp)
+    }
+}
+
+/// Returns the url chunk paths in a script are relative to, the public path
+/// of its webpack runtime or the document base.
+fn public_base(document_base: &Url, runtime: Option<&WebpackRuntime>) -> Url {
+    runtime
+        .and_then(|r| r.public_path.as_ref())
+        .and_then(|p| document_base.join(p).ok())
+        .unwrap_or_else(|| document_base.clone())
+}
+
+/// Returns the name a target's output is created under. Urls are kept whole
+/// for the sink to name, local paths are named after their last component.
+fn output_name(target: &str) -> String {
+    if target.contains("://") {
+        return target.to_owned();
+    }
+    Path::new(target)
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| target.to_owned())
+}
+
+/// Reads a source of a local map, as long as it lies below `root`. Missing
+/// files are not an error, the source is just not recovered.
+async fn read_local_source(root: &Url, url: &Url) -> Option<Result<String, ParsesmError>> {
+    let root = root.to_file_path().ok()?;
+    let path = url.to_file_path().ok()?.canonicalize().ok()?;
+    if !path.starts_with(root) {
+        return None;
+    }
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => Some(Ok(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(source) => Some(Err(ParsesmError::Io { path, source })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventFormat;
+    use crate::fetch::MockFetcher;
+    use crate::sink::MemorySink;
+
+    const MAP: &str = r#"{"version":3,"sources":["src/a.ts","src/b.ts"],"sourcesContent":["export const a = 1;",null],"mappings":"","names":[]}"#;
+
+    fn client(fetcher: MockFetcher, sink: &MemorySink) -> ParsesmClient {
+        ParsesmClient::builder()
+            .with_fetcher(Arc::new(fetcher))
+            .with_sinks(Arc::new(sink.clone()))
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn extracts_sources_into_sink() {
+        let fetcher = MockFetcher::new()
+            .with_body(
+                "https://a.com/",
+                r#"<html><script src="/js/app.js"></script></html>"#,
+            )
+            .with_body(
+                "https://a.com/js/app.js",
+                "console.log(1)\n//# sourceMappingURL=app.js.map\n",
+            )
+            .with_body("https://a.com/js/app.js.map", MAP)
+            .with_body("https://a.com/js/src/b.ts", "export const b = 2;");
+        let sink = MemorySink::new();
+        let report = client(fetcher, &sink).extract_map("https://a.com/").await;
+
+        assert_eq!(report.scripts, 1);
+        assert_eq!(report.maps, 1);
+        assert_eq!(report.written, 2);
+        assert_eq!(report.errors.total(), 0);
+        let files = sink.files();
+        assert_eq!(
+            files[Path::new("a.com/src/a.ts")],
+            b"export const a = 1;".to_vec()
+        );
+        assert_eq!(
+            files[Path::new("a.com/src/b.ts")],
+            b"export const b = 2;".to_vec()
+        );
+        assert!(files.contains_key(Path::new("a.com/manifest.json")));
+    }
+
+    #[tokio::test]
+    async fn writes_manifest_without_maps() {
+        let fetcher = MockFetcher::new()
+            .with_body(
+                "https://a.com/",
+                r#"<html><script src="/js/app.js"></script></html>"#,
+            )
+            .with_body("https://a.com/js/app.js", "console.log(1)");
+        let sink = MemorySink::new();
+        let report = client(fetcher, &sink).extract_map("https://a.com/").await;
+
+        assert_eq!(report.maps, 0);
+        let manifest = &sink.files()[Path::new("a.com/manifest.json")];
+        let manifest: serde_json::Value = serde_json::from_slice(manifest).unwrap();
+        assert_eq!(manifest["scripts"][0]["url"], "https://a.com/js/app.js");
+        assert_eq!(manifest["scripts"][0]["status"], 200);
+    }
+
+    #[tokio::test]
+    async fn follows_only_guesses_without_directive() {
+        let fetcher = Arc::new(
+            MockFetcher::new()
+                .with_body("https://a.com/", r#"<script src="/js/app.js"></script>"#)
+                .with_body(
+                    "https://a.com/js/app.js",
+                    "//# sourceMappingURL=maps/app.js.map",
+                ),
+        );
+        let client = ParsesmClient::builder()
+            .with_fetcher(fetcher.clone())
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build();
+        client.discover("https://a.com/").await.unwrap();
+
+        let requests = fetcher.requests();
+        assert!(requests.contains(&"https://a.com/js/maps/app.js.map".to_owned()));
+        assert!(!requests.contains(&"https://a.com/js/app.js.map".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn maps_resolve_against_redirected_scripts() {
+        let script = Url::parse("https://cdn.a.com/v2/app.js").unwrap();
+        let fetcher = Arc::new(
+            MockFetcher::new()
+                .with_body("https://a.com/", r#"<script src="/js/app.js"></script>"#)
+                .with_response(
+                    "https://a.com/js/app.js",
+                    Response::new(script, 200, "//# sourceMappingURL=app.js.map"),
+                ),
+        );
+        let client = ParsesmClient::builder()
+            .with_fetcher(fetcher.clone())
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build();
+        let discovery = client.discover("https://a.com/").await.unwrap();
+
+        assert_eq!(
+            discovery.scripts[0].final_url.as_ref().map(Url::as_str),
+            Some("https://cdn.a.com/v2/app.js")
+        );
+        assert!(fetcher
+            .requests()
+            .contains(&"https://cdn.a.com/v2/app.js.map".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn guessed_scripts_do_not_make_the_run_partial() {
+        let html = Response::new(
+            Url::parse("https://a.com/static/js/2.js").unwrap(),
+            200,
+            "<html></html>",
+        )
+        .with_header("content-type", "text/html");
+        let broken = Response::new(
+            Url::parse("https://a.com/js/broken.js").unwrap(),
+            200,
+            "var = ;",
+        )
+        .with_header("content-type", "application/javascript");
+        let fetcher = MockFetcher::new()
+            .with_body(
+                "https://a.com/",
+                r#"<script src="/js/app.js"></script><script src="/js/broken.js"></script>"#,
+            )
+            .with_body(
+                "https://a.com/js/app.js",
+                r#"load("static/js/1.js", "static/js/2.js")"#,
+            )
+            .with_response("https://a.com/static/js/2.js", html)
+            .with_response("https://a.com/js/broken.js", broken);
+        let sink = MemorySink::new();
+        let report = client(fetcher, &sink).extract_map("https://a.com/").await;
+
+        // the missing and the html chunk are not counted, the broken script is
+        assert_eq!(report.scripts, 4);
+        assert_eq!(report.errors.total(), 1);
+        assert_eq!(report.errors.parse, 1);
+    }
+}
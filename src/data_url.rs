@@ -0,0 +1,76 @@
+//! Decoding of inline sourcemaps embedded as `data:` urls.
+
+use percent_encoding::percent_decode_str;
+
+/// Returns true when the url is a `data:` url.
+pub fn is_data_url(url: &str) -> bool {
+    url.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Decodes the body of a `data:` url holding an inline sourcemap.
+///
+/// Both base64 and percent-encoded payloads are supported. Parameters such as
+/// `charset=utf-8` are accepted but the payload must decode to utf-8 since
+/// sourcemaps are always json. Returns `None` if the url is malformed or is
+/// not a json document.
+pub fn decode_data_url(url: &str) -> Option<String> {
+    if !is_data_url(url) {
+        return None;
+    }
+
+    let (header, payload) = url[5..].split_once(',')?;
+    let mut params = header.split(';').map(str::trim);
+
+    // an empty media type defaults to text/plain which some bundlers emit
+    let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+    if !(media_type.is_empty() || media_type.contains("json") || media_type == "text/plain") {
+        return None;
+    }
+    let is_base64 = params.any(|p| p.eq_ignore_ascii_case("base64"));
+
+    let bytes: Vec<u8> = percent_decode_str(payload).collect();
+    let bytes = if is_base64 {
+        let stripped: Vec<u8> = bytes
+            .into_iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        base64::decode(stripped).ok()?
+    } else {
+        bytes
+    };
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_data_urls() {
+        assert!(is_data_url("data:application/json,{}"));
+        assert!(is_data_url("DATA:,{}"));
+        assert!(!is_data_url("app.js.map"));
+        assert!(!is_data_url("abcdé.map"));
+        assert!(!is_data_url("dat"));
+    }
+
+    #[test]
+    fn decodes_base64() {
+        let url = "data:application/json;charset=utf-8;base64,eyJ2ZXJzaW9uIjozfQ==";
+        assert_eq!(decode_data_url(url).as_deref(), Some(r#"{"version":3}"#));
+    }
+
+    #[test]
+    fn decodes_percent_encoding() {
+        let url = "data:application/json,%7B%22version%22%3A3%7D";
+        assert_eq!(decode_data_url(url).as_deref(), Some(r#"{"version":3}"#));
+    }
+
+    #[test]
+    fn rejects_other_media_types() {
+        assert_eq!(decode_data_url("data:image/png;base64,iVBORw0KGgo="), None);
+        assert_eq!(decode_data_url("data:application/json"), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! Discovery of the scripts on a page and of the sourcemap urls belonging to them.
+
+use std::collections::HashSet;
+
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::data_url::is_data_url;
+use crate::fetch::Headers;
+
+/// Returns the url of the last `sourceMappingURL` directive in a script body.
+///
+/// Both the current `//# ` and the deprecated `//@ ` forms are accepted, as well
+/// as the block comment form `/*# sourceMappingURL=... */`. Only the comments
+/// ending the script are read, a directive followed by code is part of a
+/// string or of a bundled module.
+pub fn find_source_mapping_url(body: &str) -> Option<&str> {
+    const PREFIXES: [&str; 4] = [
+        "//# sourceMappingURL=",
+        "//@ sourceMappingURL=",
+        "/*# sourceMappingURL=",
+        "/*@ sourceMappingURL=",
+    ];
+
+    // the directive is expected near the end of the file so walk backwards
+    for line in body.lines().rev() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let is_comment = line.starts_with("//") || (line.starts_with("/*") && line.ends_with("*/"));
+        if !is_comment {
+            return None;
+        }
+
+        for prefix in PREFIXES {
+            if let Some(value) = line.strip_prefix(prefix) {
+                let value = value.trim_end_matches("*/").trim();
+                if !value.is_empty() && !value.contains(char::is_whitespace) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the map url advertised by the `SourceMap` or legacy `X-SourceMap`
+/// response header of a script.
+pub fn find_source_map_header(headers: &Headers) -> Option<&str> {
+    ["sourcemap", "x-sourcemap"]
+        .iter()
+        .filter_map(|name| headers.get(name))
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+}
+
+/// Resolves the candidate sourcemap urls for a script, in the order they
+/// should be tried.
+///
+/// The `SourceMap` response header comes first, then the `sourceMappingURL`
+/// directive. Only scripts with neither fall back to the guesses from
+/// [`guess_map_urls`].
+pub fn resolve_map_urls(
+    script_url: &str,
+    headers: &Headers,
+    body: &str,
+    keep_query: bool,
+) -> Vec<String> {
+    let mut candidates: Vec<String> = vec![];
+    let base = Url::parse(script_url).ok();
+
+    let references = [
+        find_source_map_header(headers),
+        find_source_mapping_url(body),
+    ];
+    let has_reference = references.iter().any(Option::is_some);
+    for reference in references.into_iter().flatten() {
+        // inline maps are kept as is, joining would re-encode the payload
+        if is_data_url(reference) {
+            candidates.push(reference.to_owned());
+        } else if let Some(url) = base.as_ref().and_then(|b| b.join(reference).ok()) {
+            candidates.push(url.into());
+        }
+    }
+    if let Some(base) = base.as_ref().filter(|_| !has_reference) {
+        candidates.extend(
+            guess_map_urls(base, keep_query)
+                .into_iter()
+                .map(String::from),
+        );
+    }
+
+    let mut seen = HashSet::new();
+    candidates.retain(|c| seen.insert(c.clone()));
+    candidates
+}
+
+/// Guesses where the sourcemap of a script lives when nothing references it.
+///
+/// Only the extension of the last path segment is rewritten, so `app.js` yields
+/// `app.js.map`, `app.map` and `app.mjs.map` in that order, and modules ending
+/// in `.mjs` or `.cjs` get their own suffix tried first. The fragment is always
+/// dropped and the query string is kept only when `keep_query` is set.
+pub fn guess_map_urls(script_url: &Url, keep_query: bool) -> Vec<Url> {
+    const SCRIPT_EXTENSIONS: [&str; 3] = ["js", "mjs", "cjs"];
+
+    let path = script_url.path();
+    let (dir, file_name) = match path.rfind('/') {
+        Some(idx) if idx + 1 < path.len() => path.split_at(idx + 1),
+        _ => return vec![],
+    };
+
+    let mut names = vec![format!("{}.map", file_name)];
+    if let Some((stem, ext)) = file_name.rsplit_once('.') {
+        if SCRIPT_EXTENSIONS.contains(&ext) && !stem.is_empty() {
+            names.push(format!("{}.map", stem));
+            for ext in ["js", "mjs"] {
+                names.push(format!("{}.{}.map", stem, ext));
+            }
+        }
+    }
+
+    let mut candidates: Vec<Url> = vec![];
+    for name in names {
+        let mut url = script_url.clone();
+        url.set_fragment(None);
+        if !keep_query {
+            url.set_query(None);
+        }
+        url.set_path(&format!("{}{}", dir, name));
+        if !candidates.contains(&url) {
+            candidates.push(url);
+        }
+    }
+
+    candidates
+}
+
+/// Resolves a source name from a sourcemap against the map url.
+///
+/// Returns `None` for sources that do not resolve to an http(s) url, such as
+/// the `webpack://` style names bundlers emit. Maps that are local files
+/// themselves may also resolve to `file://` urls.
+pub fn resolve_source_url(map_url: &str, source: &str) -> Option<Url> {
+    let map_url = Url::parse(map_url).ok()?;
+    let url = map_url.join(source).ok()?;
+    match url.scheme() {
+        "http" | "https" => Some(url),
+        "file" if map_url.scheme() == "file" => Some(url),
+        _ => None,
+    }
+}
+
+/// Returns the urls of every script on the page.
+///
+/// Besides `<script src>` this includes modules preloaded through
+/// `<link rel="modulepreload">` and `<link rel="preload" as="script">`, in
+/// document order. Urls are resolved against the page url, or the document's
+/// `<base href>` when it has one. Urls that are not valid are dropped.
+pub fn find_scripts(page_url: &Url, body: &str) -> Vec<Url> {
+    let mut res = vec![];
+    let doc = Html::parse_document(body);
+    let selector = Selector::parse(
+        "script[src], link[rel~=modulepreload][href], link[rel~=preload][as=script][href]",
+    )
+    .expect("failed to create selector");
+    let base = base_url(page_url, &doc);
+
+    for e in doc.select(&selector) {
+        if let Some(src) = e.value().attr("src").or_else(|| e.value().attr("href")) {
+            if let Ok(url) = base.join(src.trim()) {
+                if !res.contains(&url) {
+                    res.push(url);
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Returns the url that relative urls in the page are resolved against, the
+/// page url or the document's `<base href>` when it has one.
+pub fn document_base(page_url: &Url, body: &str) -> Url {
+    base_url(page_url, &Html::parse_document(body))
+}
+
+fn base_url(page_url: &Url, doc: &Html) -> Url {
+    let selector = Selector::parse("base[href]").expect("failed to create selector");
+    doc.select(&selector)
+        .next()
+        .and_then(|e| e.value().attr("href"))
+        .and_then(|href| page_url.join(href.trim()).ok())
+        .unwrap_or_else(|| page_url.clone())
+}
+
+/// Returns the contents of every inline script on the page, e.g. a webpack
+/// runtime inlined into the html.
+pub fn find_inline_scripts(body: &str) -> Vec<String> {
+    let doc = Html::parse_document(body);
+    let selector = Selector::parse("script:not([src])").expect("failed to create selector");
+
+    doc.select(&selector)
+        .map(|e| e.text().collect::<String>())
+        .filter(|text| !text.trim().is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guesses(url: &str, keep_query: bool) -> Vec<String> {
+        let url = Url::parse(url).unwrap();
+        guess_map_urls(&url, keep_query)
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn finds_last_directive() {
+        let body =
+            "//# sourceMappingURL=old.map\nconsole.log(1)\n//# sourceMappingURL=app.js.map\n\n";
+        assert_eq!(find_source_mapping_url(body), Some("app.js.map"));
+        assert_eq!(
+            find_source_mapping_url("//@ sourceMappingURL=a.map"),
+            Some("a.map")
+        );
+        assert_eq!(
+            find_source_mapping_url("/*# sourceMappingURL=a.css.map */"),
+            Some("a.css.map")
+        );
+        assert_eq!(find_source_mapping_url("console.log(1)"), None);
+    }
+
+    #[test]
+    fn ignores_directives_followed_by_code() {
+        let body = "var a=\"\n//# sourceMappingURL=\"+e;\nconsole.log(1)";
+        assert_eq!(find_source_mapping_url(body), None);
+        let body = "console.log(1)\n//# sourceMappingURL=app.js.map\n//# debugId=85314830\n";
+        assert_eq!(find_source_mapping_url(body), Some("app.js.map"));
+    }
+
+    #[test]
+    fn guesses_map_names() {
+        assert_eq!(
+            guesses("https://a.com/js/app.js", true),
+            [
+                "https://a.com/js/app.js.map",
+                "https://a.com/js/app.map",
+                "https://a.com/js/app.mjs.map",
+            ]
+        );
+        assert_eq!(
+            guesses("https://a.com/m.mjs", true),
+            [
+                "https://a.com/m.mjs.map",
+                "https://a.com/m.map",
+                "https://a.com/m.js.map",
+            ]
+        );
+    }
+
+    #[test]
+    fn guesses_keep_query_only_when_asked() {
+        assert_eq!(
+            guesses("https://a.com/app.js?v=1#top", true)[0],
+            "https://a.com/app.js.map?v=1"
+        );
+        assert_eq!(
+            guesses("https://a.com/app.js?v=1#top", false)[0],
+            "https://a.com/app.js.map"
+        );
+    }
+
+    #[test]
+    fn guesses_nothing_for_directories() {
+        assert!(guesses("https://a.com/js/", true).is_empty());
+    }
+
+    #[test]
+    fn directive_replaces_guesses() {
+        let body = "console.log(1)\n//# sourceMappingURL=maps/app.js.map\n";
+        assert_eq!(
+            resolve_map_urls("https://a.com/js/app.js", &Headers::new(), body, true),
+            ["https://a.com/js/maps/app.js.map"]
+        );
+        assert_eq!(
+            resolve_map_urls("https://a.com/js/app.js", &Headers::new(), "", true).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn header_comes_before_directive() {
+        let mut headers = Headers::new();
+        headers.append("sourcemap", "/h.map").unwrap();
+        let body = "//# sourceMappingURL=d.map";
+        assert_eq!(
+            resolve_map_urls("https://a.com/js/app.js", &headers, body, true),
+            ["https://a.com/h.map", "https://a.com/js/d.map"]
+        );
+    }
+
+    #[test]
+    fn non_ascii_directive() {
+        let body = "//# sourceMappingURL=abcdé.map";
+        assert_eq!(
+            resolve_map_urls("https://a.com/app.js", &Headers::new(), body, true),
+            ["https://a.com/abcd%C3%A9.map"]
+        );
+    }
+
+    #[test]
+    fn resolves_source_urls() {
+        let map = "https://a.com/js/app.js.map";
+        assert_eq!(
+            resolve_source_url(map, "../src/a.ts")
+                .map(String::from)
+                .as_deref(),
+            Some("https://a.com/src/a.ts")
+        );
+        assert_eq!(resolve_source_url(map, "webpack:///src/a.ts"), None);
+    }
+}
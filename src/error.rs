@@ -0,0 +1,210 @@
+//! The error type shared by discovery, extraction and output.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// How serious an error is for the run it happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    /// A single script, map or source was lost.
+    Warning,
+    /// The target or its output as a whole is affected.
+    Error,
+}
+
+/// Everything that can go wrong while extracting a target. Each variant keeps
+/// the url or path it relates to and the underlying cause.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParsesmError {
+    /// A url could not be parsed.
+    #[error("invalid url {url}: {source}")]
+    InvalidUrl {
+        /// The url as it was found.
+        url: String,
+        /// The underlying cause.
+        #[source]
+        source: url::ParseError,
+    },
+
+    /// A request failed without a response, or its body could not be read.
+    #[error("request to {url} failed: {source}")]
+    Network {
+        /// Url of the request.
+        url: String,
+        /// The underlying cause, usually a `reqwest::Error` for the
+        /// [`HttpFetcher`].
+        ///
+        /// [`HttpFetcher`]: crate::fetch::HttpFetcher
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A response had an unsuccessful status.
+    #[error("{url} returned http {status}")]
+    HttpStatus {
+        /// Url of the request.
+        url: String,
+        /// Status of the response.
+        status: u16,
+    },
+
+    /// A sourcemap is not valid.
+    #[error("failed to decode sourcemap {url}: {source}")]
+    Decode {
+        /// Url of the sourcemap.
+        url: String,
+        /// The underlying cause.
+        #[source]
+        source: sourcemap::Error,
+    },
+
+    /// A sourcemap is not valid json.
+    #[error("invalid json in {url}: {source}")]
+    Json {
+        /// Url of the sourcemap.
+        url: String,
+        /// The underlying cause.
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// A script could not be parsed, so what was found in it may be
+    /// incomplete.
+    #[error("failed to parse script {url}: {message}")]
+    Parse {
+        /// Url of the script.
+        url: String,
+        /// The first syntax error.
+        message: String,
+    },
+
+    /// Reading or writing a file failed.
+    #[error("failed to access {}: {source}", path.display())]
+    Io {
+        /// Path of the file.
+        path: PathBuf,
+        /// The underlying cause.
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A source would have been written outside the output directory.
+    #[error("refusing to write source {name:?}: {reason}")]
+    PathSafety {
+        /// Name of the source in the map.
+        name: String,
+        /// Why the source was rejected.
+        reason: String,
+    },
+}
+
+impl ParsesmError {
+    /// Wraps a sourcemap decoding error, keeping json errors apart.
+    pub fn decode(url: &str, source: sourcemap::Error) -> Self {
+        match source {
+            sourcemap::Error::BadJson(source) => ParsesmError::Json {
+                url: url.to_owned(),
+                source,
+            },
+            source => ParsesmError::Decode {
+                url: url.to_owned(),
+                source,
+            },
+        }
+    }
+
+    /// Short machine readable name of the variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParsesmError::InvalidUrl { .. } => "invalid_url",
+            ParsesmError::Network { .. } => "network",
+            ParsesmError::HttpStatus { .. } => "http_status",
+            ParsesmError::Decode { .. } => "decode",
+            ParsesmError::Json { .. } => "json",
+            ParsesmError::Parse { .. } => "parse",
+            ParsesmError::Io { .. } => "io",
+            ParsesmError::PathSafety { .. } => "path_safety",
+        }
+    }
+
+    /// How serious the error is, failed requests and file access are errors and
+    /// the loss of a single map or source is a warning.
+    pub fn level(&self) -> Level {
+        match self {
+            ParsesmError::Network { .. } | ParsesmError::Io { .. } => Level::Error,
+            _ => Level::Warning,
+        }
+    }
+
+    /// The url the error relates to, if any.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            ParsesmError::InvalidUrl { url, .. }
+            | ParsesmError::Network { url, .. }
+            | ParsesmError::HttpStatus { url, .. }
+            | ParsesmError::Decode { url, .. }
+            | ParsesmError::Json { url, .. }
+            | ParsesmError::Parse { url, .. } => Some(url),
+            ParsesmError::Io { .. } | ParsesmError::PathSafety { .. } => None,
+        }
+    }
+
+    /// The http status of the response that caused the error, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ParsesmError::HttpStatus { status, .. } => Some(*status),
+            ParsesmError::Network { source, .. } => source
+                .downcast_ref::<reqwest::Error>()
+                .and_then(reqwest::Error::status)
+                .map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// The error message followed by the underlying causes it does not
+    /// already mention.
+    pub fn chain(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self).and_then(|s| s.source());
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !message.contains(&cause_message) {
+                message.push_str(": ");
+                message.push_str(&cause_message);
+            }
+            source = cause.source();
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_errors_are_kept_apart() {
+        let url = "https://a.com/app.js.map";
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let error = ParsesmError::decode(url, sourcemap::Error::BadJson(json));
+        assert_eq!(error.kind(), "json");
+        assert_eq!(error.url(), Some(url));
+
+        let error = ParsesmError::decode(url, sourcemap::Error::IncompatibleSourceMap);
+        assert_eq!(error.kind(), "decode");
+    }
+
+    #[test]
+    fn http_errors_are_warnings() {
+        let error = ParsesmError::HttpStatus {
+            url: "https://a.com/".to_owned(),
+            status: 404,
+        };
+        assert_eq!(error.status(), Some(404));
+        assert_eq!(error.level(), Level::Warning);
+        assert_eq!(error.to_string(), "https://a.com/ returned http 404");
+    }
+}
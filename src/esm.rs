@@ -0,0 +1,117 @@
+//! Finding the modules an ES module imports.
+//!
+//! Vite and Rollup split apps into modules loading each other through static
+//! `import` declarations and dynamic `import()` calls, so following them
+//! reaches chunks that no page references.
+
+use oxc_ast::ast::{
+    ExportAllDeclaration, ExportNamedDeclaration, Expression, ImportDeclaration, ImportExpression,
+    Program,
+};
+use oxc_ast_visit::{walk, Visit};
+use url::Url;
+
+/// Resolves the import specifiers of the module at `module_url`.
+///
+/// Bare specifiers such as `react` are skipped since they cannot be resolved
+/// without an import map.
+pub(crate) fn resolve_imports(module_url: &Url, specifiers: Vec<String>) -> Vec<Url> {
+    let mut imports: Vec<Url> = vec![];
+    for specifier in specifiers {
+        let relative = ["./", "../", "/"].iter().any(|p| specifier.starts_with(p));
+        if !relative && !specifier.starts_with("http://") && !specifier.starts_with("https://") {
+            continue;
+        }
+        if let Ok(url) = module_url.join(&specifier) {
+            if !imports.contains(&url) {
+                imports.push(url);
+            }
+        }
+    }
+    imports
+}
+
+/// Returns the literal specifiers of every import in a parsed module.
+///
+/// Static imports, `export ... from` re-exports and dynamic imports with a
+/// literal specifier are found.
+pub(crate) fn find_specifiers(program: &Program<'_>) -> Vec<String> {
+    let mut collector = Collector { specifiers: vec![] };
+    collector.visit_program(program);
+    collector.specifiers
+}
+
+struct Collector {
+    specifiers: Vec<String>,
+}
+
+impl<'a> Visit<'a> for Collector {
+    fn visit_import_declaration(&mut self, it: &ImportDeclaration<'a>) {
+        self.specifiers.push(it.source.value.to_string());
+    }
+
+    fn visit_export_named_declaration(&mut self, it: &ExportNamedDeclaration<'a>) {
+        if let Some(source) = &it.source {
+            self.specifiers.push(source.value.to_string());
+        }
+        walk::walk_export_named_declaration(self, it);
+    }
+
+    fn visit_export_all_declaration(&mut self, it: &ExportAllDeclaration<'a>) {
+        self.specifiers.push(it.source.value.to_string());
+    }
+
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        match &it.source {
+            Expression::StringLiteral(source) => self.specifiers.push(source.value.to_string()),
+            Expression::TemplateLiteral(source) if source.expressions.is_empty() => {
+                let cooked = source.quasis.first().and_then(|q| q.value.cooked.as_ref());
+                self.specifiers.extend(cooked.map(|c| c.to_string()));
+            }
+            _ => {}
+        }
+        walk::walk_import_expression(self, it);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literals::find_script_references;
+
+    fn imports(body: &str) -> Vec<String> {
+        let url = Url::parse("https://a.com/p/main.js").unwrap();
+        find_script_references(&url, body)
+            .0
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn finds_static_and_dynamic_imports() {
+        let body = r#"import { a } from "./a.js";
+            import "../b.js";
+            export * from "/c.js";
+            export { d } from "./d.js";
+            import React from "react";
+            const e = () => import(`./e.js`);"#;
+        assert_eq!(
+            imports(body),
+            [
+                "https://a.com/p/a.js",
+                "https://a.com/b.js",
+                "https://a.com/c.js",
+                "https://a.com/p/d.js",
+                "https://a.com/p/e.js"
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_strings_and_comments() {
+        let body = r#"const s = "import('./no.js')"; // import "./comment.js"
+            import(name);"#;
+        assert!(imports(body).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Progress events emitted while discovering and extracting sourcemaps.
+
+use std::fmt::Display;
+use std::sync::Mutex;
+
+use ansi_term::Colour;
+use serde::Serialize;
+
+use crate::error::{Level, ParsesmError};
+use crate::report::ExtractReport;
+
+/// Something that happened during a run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// A target page was fetched.
+    PageFetched {
+        /// Url of the page after redirects.
+        url: String,
+        /// Status of the page response.
+        status: u16,
+    },
+    /// A script was found on a page and is in scope.
+    ScriptFound {
+        /// Url of the script.
+        url: String,
+    },
+    /// The framework of a page was detected.
+    FrameworkDetected {
+        /// Name of the framework.
+        framework: String,
+        /// Version of the framework, if the page gives it away.
+        version: Option<String>,
+        /// Id of the deployed build.
+        build: Option<String>,
+        /// Number of chunks the framework lists.
+        chunks: usize,
+    },
+    /// A route of the app was found in a framework manifest.
+    RouteFound {
+        /// Path of the route.
+        route: String,
+        /// Urls of the chunks the route loads.
+        chunks: Vec<String>,
+    },
+    /// A map url was tried for a script.
+    MapCandidateTried {
+        /// Url of the script.
+        script_url: String,
+        /// Url of the map candidate.
+        map_url: String,
+        /// Status of the response, `None` when no response was received.
+        status: Option<u16>,
+        /// Whether the map was found.
+        found: bool,
+    },
+    /// A sourcemap was decoded.
+    MapLoaded {
+        /// Url of the map.
+        url: String,
+        /// Number of sources in the map.
+        sources: usize,
+        /// Hash of the map as it was served.
+        sha256: String,
+    },
+    /// A source was written to disk.
+    SourceWritten {
+        /// Name of the source in the map.
+        name: String,
+        /// Where the source was written, the output location and its path.
+        path: String,
+        /// Size of the source in bytes.
+        size: usize,
+        /// Hash of the source.
+        sha256: String,
+    },
+    /// Something went wrong, see [`ParsesmError`].
+    Error {
+        /// How serious the error is.
+        level: Level,
+        /// The [`ParsesmError::kind`] of the error.
+        kind: &'static str,
+        /// The url the error relates to, if any.
+        url: Option<String>,
+        /// The error with its causes.
+        message: String,
+    },
+    /// A target is done.
+    Finished(ExtractReport),
+}
+
+/// How events are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFormat {
+    /// Human readable progress on stderr.
+    Text,
+    /// Events are collected and handed out with [`Reporter::take_events`].
+    Json,
+    /// Every event is printed to stdout as a line of json.
+    Ndjson,
+}
+
+/// Receives the events of a run and reports them in the chosen format.
+pub struct Reporter {
+    format: EventFormat,
+    events: Mutex<Vec<Event>>,
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self::new(EventFormat::Text)
+    }
+}
+
+impl Reporter {
+    /// Creates a reporter using the given format.
+    pub fn new(format: EventFormat) -> Self {
+        Self {
+            format,
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    /// Reports an event, collecting it in json mode and printing it otherwise.
+    pub fn emit(&self, event: Event) {
+        match self.format {
+            EventFormat::Text => print_text(&event),
+            EventFormat::Json => self.events.lock().expect("reporter poisoned").push(event),
+            EventFormat::Ndjson => {
+                if let Ok(line) = serde_json::to_string(&event) {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+
+    /// Reports an error with its full chain of causes.
+    pub fn error(&self, error: &ParsesmError) {
+        self.emit(Event::Error {
+            level: error.level(),
+            kind: error.kind(),
+            url: error.url().map(display_url),
+            message: error.chain(),
+        });
+    }
+
+    /// Prints a progress message, only shown in text mode.
+    pub fn info<D: Display>(&self, message: D) {
+        if self.format == EventFormat::Text {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Returns the events collected so far in json mode.
+    pub fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.events.lock().expect("reporter poisoned"))
+    }
+}
+
+fn print_text(event: &Event) {
+    match event {
+        Event::SourceWritten { path, size, .. } => {
+            let path = std::path::Path::new(path);
+            eprintln!(
+                "found original source for module {} and file {} of size {}",
+                path.parent().unwrap_or(path).display(),
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                size
+            );
+        }
+        Event::Error { level, message, .. } => match level {
+            Level::Warning => eprintln!("{} {}", Colour::Yellow.paint("warning:"), message),
+            Level::Error => eprintln!("{} {}", Colour::Red.paint("error:"), message),
+        },
+        _ => {}
+    }
+}
+
+/// Shortens `data:` urls to their header so events stay readable.
+pub fn display_url(url: &str) -> String {
+    if crate::data_url::is_data_url(url) {
+        match url.split_once(',') {
+            Some((header, _)) => format!("{},…", header),
+            None => "data:…".to_owned(),
+        }
+    } else {
+        url.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_tagged() {
+        let event = Event::ScriptFound {
+            url: "https://a.com/app.js".to_owned(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"event": "script_found", "url": "https://a.com/app.js"})
+        );
+    }
+
+    #[test]
+    fn json_reporter_collects_events() {
+        let reporter = Reporter::new(EventFormat::Json);
+        reporter.emit(Event::ScriptFound {
+            url: "https://a.com/app.js".to_owned(),
+        });
+        reporter.info("not an event");
+        assert_eq!(reporter.take_events().len(), 1);
+        assert!(reporter.take_events().is_empty());
+    }
+
+    #[test]
+    fn data_urls_are_shortened() {
+        assert_eq!(
+            display_url("data:application/json;base64,eyJ9"),
+            "data:application/json;base64,…"
+        );
+        assert_eq!(display_url("https://a.com/a.map"), "https://a.com/a.map");
+    }
+}
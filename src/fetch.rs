@@ -0,0 +1,500 @@
+//! Where pages, scripts and maps are fetched from.
+//!
+//! [`ParsesmClient`](crate::ParsesmClient) only talks to the outside world
+//! through a [`Fetcher`], so the same discovery and extraction can run against
+//! live sites, mirrored copies on disk or fixtures held in memory.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION,
+    PROXY_AUTHORIZATION,
+};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::error::ParsesmError;
+use crate::paths::host_dir;
+use crate::warc::{HttpExchange, WarcWriter};
+
+/// Headers of a request or response. Names are case insensitive and a name
+/// may appear several times.
+#[derive(Debug, Clone, Default)]
+pub struct Headers(pub(crate) HeaderMap);
+
+impl Headers {
+    /// Creates empty headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a header, keeping earlier values of the same name.
+    ///
+    /// Fails with the reason when the name or value is not valid in http.
+    pub fn append(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| e.to_string())?;
+        let value = HeaderValue::from_str(value).map_err(|e| e.to_string())?;
+        self.0.append(name, value);
+        Ok(())
+    }
+
+    /// Returns the first value of `name`, `None` when it is missing or not
+    /// text.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name)?.to_str().ok()
+    }
+
+    /// Returns every header with a text value, names in lowercase.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+    }
+}
+
+/// A response to a GET request, successful or not.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Response {
+    /// Url of the response after redirects.
+    pub final_url: Url,
+    /// Status of the response.
+    pub status: u16,
+    /// Headers of the response.
+    pub headers: Headers,
+    /// Body of the response.
+    pub body: String,
+}
+
+impl Response {
+    /// Creates a response without headers.
+    pub fn new(final_url: Url, status: u16, body: impl Into<String>) -> Self {
+        Self {
+            final_url,
+            status,
+            headers: Headers::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Adds a header, e.g. `SourceMap`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the name or value is not valid in http.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        if let Err(e) = self.headers.append(name, value) {
+            panic!("invalid header `{}`: {}", name, e);
+        }
+        self
+    }
+
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether the `Content-Type` of the response is javascript.
+    pub fn is_javascript(&self) -> bool {
+        self.headers
+            .get(CONTENT_TYPE.as_str())
+            .is_some_and(|value| {
+                let value = value.to_ascii_lowercase();
+                value.contains("javascript") || value.contains("ecmascript")
+            })
+    }
+}
+
+/// Performs GET requests for a [`ParsesmClient`](crate::ParsesmClient).
+///
+/// Unsuccessful statuses are returned as responses, errors are reserved for
+/// requests that did not get a response at all.
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    /// Fetches `url`.
+    async fn get(&self, url: &Url) -> Result<Response, ParsesmError>;
+}
+
+/// Redirects followed for a single request before giving up, as many as
+/// reqwest follows by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// Headers that are not sent on when a redirect leaves the origin.
+const SENSITIVE_HEADERS: [HeaderName; 3] = [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION];
+
+/// Fetches over http(s) with reqwest.
+///
+/// Redirects are followed one hop at a time, so every hop can be recorded.
+/// Credentials among the headers are dropped once a redirect leaves the
+/// origin of the request.
+pub struct HttpFetcher {
+    client: reqwest::Client,
+    /// Headers sent with every request.
+    headers: HeaderMap,
+    recorder: Option<Arc<WarcWriter>>,
+}
+
+impl HttpFetcher {
+    /// Creates a fetcher sending `headers` with every request.
+    ///
+    /// Tls certificates and hostnames are only checked when `verify_tls` is
+    /// set since targets frequently serve broken certificates.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the tls backend cannot be initialized.
+    pub fn with_options(headers: &Headers, verify_tls: bool, timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .use_native_tls()
+            .danger_accept_invalid_hostnames(!verify_tls)
+            .danger_accept_invalid_certs(!verify_tls)
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(timeout)
+            .pool_max_idle_per_host(5)
+            .pool_idle_timeout(Duration::from_secs(15))
+            .build()
+            .expect("failed to build client");
+        Self {
+            client,
+            headers: headers.0.clone(),
+            recorder: None,
+        }
+    }
+
+    /// Records every exchange into a WARC archive, including each redirect
+    /// hop. Requests that fail without a response leave no record.
+    pub fn with_recorder(mut self, recorder: Option<Arc<WarcWriter>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    async fn get(&self, url: &Url) -> Result<Response, ParsesmError> {
+        let network_error =
+            |source: Box<dyn std::error::Error + Send + Sync>| ParsesmError::Network {
+                url: url.to_string(),
+                source,
+            };
+
+        let mut url = url.clone();
+        let mut headers = self.headers.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let resp = self
+                .client
+                .get(url.clone())
+                .headers(headers.clone())
+                .send()
+                .await
+                .map_err(|e| network_error(Box::new(e)))?;
+            let status = resp.status().as_u16();
+            let response_headers = resp.headers().clone();
+            let version = resp.version();
+            let bytes = resp.bytes().await.map_err(|e| network_error(Box::new(e)))?;
+
+            if let Some(recorder) = &self.recorder {
+                recorder.record(&HttpExchange {
+                    url: &url,
+                    request_headers: &headers,
+                    version,
+                    status,
+                    response_headers: &response_headers,
+                    body: &bytes,
+                })?;
+            }
+
+            let location = match redirect_target(&url, status, &response_headers) {
+                Some(location) => location,
+                None => {
+                    return Ok(Response {
+                        final_url: url,
+                        status,
+                        headers: Headers(response_headers),
+                        body: String::from_utf8_lossy(&bytes).into_owned(),
+                    })
+                }
+            };
+            if location.origin() != url.origin() {
+                for name in &SENSITIVE_HEADERS {
+                    headers.remove(name);
+                }
+            }
+            url = location;
+        }
+
+        Err(network_error("too many redirects".into()))
+    }
+}
+
+/// Returns where a redirect response points, `None` for other responses.
+fn redirect_target(url: &Url, status: u16, headers: &HeaderMap) -> Option<Url> {
+    if !matches!(status, 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    let location = headers.get(LOCATION)?.to_str().ok()?;
+    url.join(location)
+        .ok()
+        .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+}
+
+/// Serves files from a mirrored copy of one or more sites.
+///
+/// By default a url is looked up as `<root>/<host>/<path>`, the layout `wget
+/// --mirror` produces. Directories are served through their `index.html`.
+/// Missing files are answered with a 404 like a web server would.
+pub struct LocalFetcher {
+    root: PathBuf,
+    host_dirs: bool,
+}
+
+impl LocalFetcher {
+    /// Creates a fetcher serving the mirror under `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            host_dirs: true,
+        }
+    }
+
+    /// Sets whether the mirror has a directory per host. Without them every
+    /// host is served from `root` directly.
+    pub fn with_host_dirs(mut self, host_dirs: bool) -> Self {
+        self.host_dirs = host_dirs;
+        self
+    }
+
+    /// Returns the candidate files for a url, `None` if its path cannot be
+    /// mapped safely.
+    fn paths(&self, url: &Url) -> Option<Vec<PathBuf>> {
+        let mut path = self.root.clone();
+        if self.host_dirs {
+            path.push(host_dir(url.as_str()));
+        }
+        for segment in url.path_segments().into_iter().flatten() {
+            let segment = percent_decode_str(segment).decode_utf8().ok()?;
+            if segment == "." || segment == ".." || segment.contains(['/', '\\', '\0']) {
+                return None;
+            }
+            if !segment.is_empty() {
+                path.push(segment.as_ref());
+            }
+        }
+        if url.path().ends_with('/') {
+            path.push("index.html");
+        }
+
+        let mut paths = vec![];
+        if let Some(query) = url.query() {
+            // mirrors keep the query string as part of the file name
+            let mut name = path.clone().into_os_string();
+            name.push("?");
+            name.push(query);
+            paths.push(PathBuf::from(name));
+        }
+        paths.push(path);
+        Some(paths)
+    }
+}
+
+#[async_trait]
+impl Fetcher for LocalFetcher {
+    async fn get(&self, url: &Url) -> Result<Response, ParsesmError> {
+        let paths = match self.paths(url) {
+            Some(paths) => paths,
+            None => return Ok(Response::new(url.clone(), 404, "")),
+        };
+
+        for mut path in paths {
+            if path.is_dir() {
+                path.push("index.html");
+            }
+            match tokio::fs::read(&path).await {
+                Ok(body) => {
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    return Ok(Response::new(url.clone(), 200, body));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(source) => return Err(ParsesmError::Io { path, source }),
+            }
+        }
+
+        Ok(Response::new(url.clone(), 404, ""))
+    }
+}
+
+/// Serves canned responses from memory, for tests and fixtures.
+///
+/// Urls without a response are answered with a 404. Every requested url is
+/// recorded and can be inspected with [`MockFetcher::requests`].
+#[derive(Default)]
+pub struct MockFetcher {
+    responses: HashMap<String, Response>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl MockFetcher {
+    /// Creates a fetcher without any responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `body` with a 200 status at `url`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` is not a valid url.
+    pub fn with_body(self, url: &str, body: impl Into<String>) -> Self {
+        let parsed = Url::parse(url).expect("invalid mock url");
+        self.with_response(url, Response::new(parsed, 200, body))
+    }
+
+    /// Serves `response` at `url`.
+    pub fn with_response(mut self, url: &str, response: Response) -> Self {
+        self.responses.insert(url.to_owned(), response);
+        self
+    }
+
+    /// The urls requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Fetcher for MockFetcher {
+    async fn get(&self, url: &Url) -> Result<Response, ParsesmError> {
+        self.requests.lock().unwrap().push(url.to_string());
+        Ok(self
+            .responses
+            .get(url.as_str())
+            .cloned()
+            .unwrap_or_else(|| Response::new(url.clone(), 404, "")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::warc::read_warc;
+
+    async fn get(fetcher: &dyn Fetcher, url: &str) -> Response {
+        fetcher.get(&Url::parse(url).unwrap()).await.unwrap()
+    }
+
+    /// Answers one connection after another with the responses for the local
+    /// port it listens on, keeping the head of every request.
+    async fn serve<F>(responses: F) -> (u16, Arc<Mutex<Vec<String>>>)
+    where
+        F: FnOnce(u16) -> Vec<String>,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let responses = responses(port);
+        let requests = Arc::new(Mutex::new(vec![]));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                seen.lock().unwrap().push(request);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (port, requests)
+    }
+
+    #[tokio::test]
+    async fn serves_mirrors_by_host() {
+        let root = std::env::temp_dir().join(format!("parsesm-{}-mirror", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a.com/js")).unwrap();
+        std::fs::write(root.join("a.com/index.html"), "page").unwrap();
+        std::fs::write(root.join("a.com/js/app.js"), "app").unwrap();
+        std::fs::write(root.join("a.com/js/app.js?v=1"), "app v1").unwrap();
+
+        let fetcher = LocalFetcher::new(&root);
+        assert_eq!(get(&fetcher, "https://a.com/").await.body, "page");
+        assert_eq!(
+            get(&fetcher, "https://a.com/js/app.js?v=1").await.body,
+            "app v1"
+        );
+        assert_eq!(
+            get(&fetcher, "https://a.com/js/app.js?v=2").await.body,
+            "app"
+        );
+        assert_eq!(get(&fetcher, "https://b.com/js/app.js").await.status, 404);
+        assert_eq!(
+            get(&fetcher, "https://a.com/js/..%2F..%2Fsecret")
+                .await
+                .status,
+            404
+        );
+
+        let fetcher = LocalFetcher::new(root.join("a.com")).with_host_dirs(false);
+        assert_eq!(get(&fetcher, "https://b.com/js/app.js").await.body, "app");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_records_requests() {
+        let fetcher = MockFetcher::new().with_body("https://a.com/app.js", "app");
+        assert_eq!(get(&fetcher, "https://a.com/app.js").await.body, "app");
+        assert_eq!(get(&fetcher, "https://a.com/app.js.map").await.status, 404);
+        assert_eq!(
+            fetcher.requests(),
+            ["https://a.com/app.js", "https://a.com/app.js.map"]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_every_redirect_hop() {
+        let (port, requests) = serve(|port| {
+            vec![
+                format!(
+                    "HTTP/1.1 302 Found\r\nLocation: http://localhost:{}/b\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    port
+                ),
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_owned(),
+            ]
+        })
+        .await;
+        let path = std::env::temp_dir().join(format!("parsesm-{}-hops.warc", std::process::id()));
+        let mut headers = Headers::new();
+        headers.append("cookie", "session=1").unwrap();
+        let fetcher = HttpFetcher::with_options(&headers, true, Duration::from_secs(5))
+            .with_recorder(Some(Arc::new(WarcWriter::create(&path).unwrap())));
+
+        let url = format!("http://127.0.0.1:{}/a", port);
+        let resp = get(&fetcher, &url).await;
+        assert_eq!(
+            resp.final_url.as_str(),
+            format!("http://localhost:{}/b", port)
+        );
+        assert_eq!(resp.body, "ok");
+
+        // credentials stay with the origin they were meant for
+        let requests = requests.lock().unwrap().clone();
+        assert!(requests[0].contains("cookie: session=1"));
+        assert!(!requests[1].contains("cookie"));
+
+        let capture = read_warc(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(capture.len(), 2);
+    }
+}
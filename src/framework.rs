@@ -0,0 +1,414 @@
+//! Recognizing the framework a page is built with and enumerating its
+//! chunks.
+//!
+//! [`find_scripts`](crate::discovery::find_scripts) only sees what a page
+//! references itself. Most frameworks also publish which chunks the rest of
+//! the app loads, each in its own way, and a [`FrameworkDetector`] reads that
+//! for one of them.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use url::Url;
+
+use crate::angular::AngularDetector;
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::fetch::Response;
+use crate::nextjs::NextDetector;
+use crate::nuxt::NuxtDetector;
+use crate::remix::RemixDetector;
+use crate::sveltekit::SvelteKitDetector;
+use crate::vite::ViteDetector;
+
+/// The page a detector looks at.
+#[non_exhaustive]
+pub struct Page<'a> {
+    /// Url of the page after redirects.
+    pub url: &'a Url,
+    /// The html of the page.
+    pub body: &'a str,
+    /// The scripts the page references that are in scope, see
+    /// [`find_scripts`](crate::discovery::find_scripts).
+    pub scripts: &'a [Url],
+}
+
+impl<'a> Page<'a> {
+    /// Creates the page a detector looks at.
+    pub fn new(url: &'a Url, body: &'a str, scripts: &'a [Url]) -> Self {
+        Self { url, body, scripts }
+    }
+}
+
+/// A framework found on a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Detection {
+    /// Name of the framework.
+    pub framework: String,
+    /// Version of the framework, as precise as the page gives it away, `None`
+    /// for frameworks that do not publish it.
+    pub version: Option<String>,
+    /// Id of the deployed build, e.g. the Next.js build id.
+    pub build: Option<String>,
+    /// Every chunk of the app the framework lists.
+    pub chunks: Vec<Url>,
+    /// The routes of the app and the chunks each of them loads.
+    pub routes: BTreeMap<String, Vec<Url>>,
+}
+
+impl Detection {
+    /// Creates a detection of `framework` without any chunks.
+    pub fn new(framework: &str) -> Self {
+        Self {
+            framework: framework.to_owned(),
+            version: None,
+            build: None,
+            chunks: vec![],
+            routes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the version of the framework.
+    pub fn with_version(mut self, version: Option<String>) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets the id of the build.
+    pub fn with_build(mut self, build: Option<String>) -> Self {
+        self.build = build;
+        self
+    }
+
+    /// Adds chunks, skipping those already listed.
+    pub fn add_chunks<I: IntoIterator<Item = Url>>(&mut self, chunks: I) {
+        for chunk in chunks {
+            if !self.chunks.contains(&chunk) {
+                self.chunks.push(chunk);
+            }
+        }
+    }
+
+    /// Adds a route and its chunks, which are added to the chunks as well.
+    pub fn add_route(&mut self, route: String, chunks: Vec<Url>) {
+        self.add_chunks(chunks.iter().cloned());
+        self.routes.insert(route, chunks);
+    }
+}
+
+/// Recognizes pages built with one framework and lists their chunks.
+///
+/// Detectors may fetch the manifests of the framework through the client, so
+/// requests stay within its limits and fetcher. Files outside of the
+/// client's [`scope`](ParsesmClient::scope) are not fetched.
+#[async_trait]
+pub trait FrameworkDetector: Send + Sync {
+    /// Returns what the page reveals about the framework, `None` when it is
+    /// not built with it. Errors only limit what is found, they are added to
+    /// `errors` to be reported and counted with the page.
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection>;
+}
+
+/// Returns a detector for every framework supported out of the box.
+pub fn default_detectors() -> Vec<Arc<dyn FrameworkDetector>> {
+    vec![
+        Arc::new(NextDetector),
+        Arc::new(NuxtDetector),
+        Arc::new(SvelteKitDetector),
+        Arc::new(RemixDetector),
+        Arc::new(AngularDetector),
+        Arc::new(ViteDetector),
+    ]
+}
+
+/// Fetches a file a detector needs, `None` when it is out of scope for the
+/// page.
+pub(crate) async fn fetch_in_scope(
+    page: &Page<'_>,
+    client: &ParsesmClient,
+    url: &Url,
+) -> Option<Result<Response, ParsesmError>> {
+    if !client.scope().allows(page.url, url) {
+        return None;
+    }
+    Some(client.fetch(url.as_str()).await)
+}
+
+/// Like [`fetch_in_scope`], adding a failed request to `errors`.
+pub(crate) async fn fetch_file(
+    page: &Page<'_>,
+    client: &ParsesmClient,
+    url: &Url,
+    errors: &mut Vec<ParsesmError>,
+) -> Option<Response> {
+    match fetch_in_scope(page, client, url).await? {
+        Ok(resp) => Some(resp),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+/// Returns every script the page refers to through a path containing
+/// `marker`, e.g. `/_nuxt/`, as written in the page.
+pub(crate) fn find_references(body: &str, marker: &str) -> Vec<String> {
+    const DELIMITERS: [char; 11] = ['"', '\'', '`', '\\', '(', ')', '=', ',', ' ', '<', '>'];
+
+    let mut references: Vec<String> = vec![];
+    for (idx, _) in body.match_indices(marker) {
+        let start = body[..idx]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| DELIMITERS.contains(c) || c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let end = body[idx..]
+            .find(|c: char| DELIMITERS.contains(&c) || c.is_whitespace())
+            .map_or(body.len(), |i| idx + i);
+        let reference = &body[start..end];
+        let is_script = [".js", ".mjs"].iter().any(|ext| reference.ends_with(ext));
+        if is_script && !references.iter().any(|r| r == reference) {
+            references.push(reference.to_owned());
+        }
+    }
+    references
+}
+
+/// Returns the value of the first `attribute="..."` in the page.
+pub(crate) fn find_attribute<'a>(body: &'a str, attribute: &str) -> Option<&'a str> {
+    let (_, rest) = body.split_once(&format!("{}=", attribute))?;
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    rest[1..].split(quote).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Discovery;
+    use crate::events::{EventFormat, Reporter};
+    use crate::fetch::{MockFetcher, Response};
+
+    async fn discover(fetcher: MockFetcher) -> Discovery {
+        ParsesmClient::builder()
+            .with_fetcher(Arc::new(fetcher))
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build()
+            .discover("https://a.com/")
+            .await
+            .unwrap()
+    }
+
+    /// Returns the single framework found on the page.
+    async fn detect(fetcher: MockFetcher) -> Detection {
+        let mut frameworks = discover(fetcher).await.frameworks;
+        assert_eq!(frameworks.len(), 1, "{:?}", frameworks);
+        frameworks.remove(0)
+    }
+
+    fn chunks(detection: &Detection) -> Vec<&str> {
+        detection.chunks.iter().map(Url::as_str).collect()
+    }
+
+    #[tokio::test]
+    async fn nuxt() {
+        let page = r#"<div id="__nuxt"></div>
+            <script type="module" src="/_nuxt/entry.4a1b.js" crossorigin></script>
+            <link rel="modulepreload" as="script" crossorigin href="/_nuxt/about.9c2d.js">
+            <script>window.__NUXT__={};window.__NUXT__.config={public:{},app:{baseURL:"/",buildId:"b1",buildAssetsDir:"/_nuxt/",cdnURL:""}}</script>"#;
+        let fetcher = MockFetcher::new()
+            .with_body("https://a.com/", page)
+            .with_body(
+                "https://a.com/_nuxt/builds/meta/b1.json",
+                r#"{"id":"b1","prerendered":["/","/about"]}"#,
+            );
+        let nuxt = detect(fetcher).await;
+        assert_eq!(nuxt.framework, "nuxt");
+        assert_eq!(nuxt.version.as_deref(), Some("3"));
+        assert_eq!(nuxt.build.as_deref(), Some("b1"));
+        assert_eq!(
+            chunks(&nuxt),
+            [
+                "https://a.com/_nuxt/entry.4a1b.js",
+                "https://a.com/_nuxt/about.9c2d.js"
+            ]
+        );
+        assert_eq!(nuxt.routes.keys().collect::<Vec<_>>(), ["/", "/about"]);
+    }
+
+    #[tokio::test]
+    async fn sveltekit() {
+        let page = r#"<link href="/_app/immutable/entry/start.abc.js" rel="modulepreload">
+            <script>{__sveltekit_1x2y={base:""};Promise.all([import("/_app/immutable/entry/start.abc.js"),import("/_app/immutable/entry/app.def.js")])}</script>"#;
+        let fetcher = MockFetcher::new()
+            .with_body("https://a.com/", page)
+            .with_body(
+                "https://a.com/_app/version.json",
+                r#"{"version":"1700000000"}"#,
+            );
+        let sveltekit = detect(fetcher).await;
+        assert_eq!(sveltekit.framework, "sveltekit");
+        assert_eq!(sveltekit.version, None);
+        assert_eq!(sveltekit.build.as_deref(), Some("1700000000"));
+        assert_eq!(
+            chunks(&sveltekit),
+            [
+                "https://a.com/_app/immutable/entry/start.abc.js",
+                "https://a.com/_app/immutable/entry/app.def.js"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn remix_inline_manifest() {
+        let page = r#"<script>window.__remixManifest={"version":"f1e2","entry":{"module":"/build/entry.client-A.js","imports":["/build/_shared/chunk-B.js"]},"routes":{"root":{"module":"/build/root-C.js","imports":[]},"routes/_index":{"module":"/build/routes/_index-D.js"}}};</script>"#;
+        let remix = detect(MockFetcher::new().with_body("https://a.com/", page)).await;
+        assert_eq!(remix.framework, "remix");
+        assert_eq!(remix.version, None);
+        assert_eq!(remix.build.as_deref(), Some("f1e2"));
+        assert_eq!(
+            chunks(&remix),
+            [
+                "https://a.com/build/entry.client-A.js",
+                "https://a.com/build/_shared/chunk-B.js",
+                "https://a.com/build/root-C.js",
+                "https://a.com/build/routes/_index-D.js"
+            ]
+        );
+        assert_eq!(
+            remix.routes.keys().collect::<Vec<_>>(),
+            ["root", "routes/_index"]
+        );
+    }
+
+    #[tokio::test]
+    async fn react_router_manifest_script() {
+        let page = r#"<script src="/assets/manifest-9a8b.js"></script>"#;
+        let fetcher = MockFetcher::new()
+            .with_body("https://a.com/", page)
+            .with_body(
+                "https://a.com/assets/manifest-9a8b.js",
+                r#"window.__reactRouterManifest={"entry":{"module":"/assets/entry.client-E.js"},"routes":{}};"#,
+            );
+        let router = detect(fetcher).await;
+        assert_eq!(router.framework, "react-router");
+        assert_eq!(chunks(&router), ["https://a.com/assets/entry.client-E.js"]);
+    }
+
+    #[tokio::test]
+    async fn angular() {
+        let page = r#"<app-root ng-version="17.0.8"></app-root>
+            <script src="runtime.1a.js" defer></script>
+            <script src="polyfills.2b.js" defer></script>
+            <script src="main.3c.js" defer></script>"#;
+        let fetcher = Arc::new(
+            MockFetcher::new()
+                .with_body("https://a.com/", page)
+                .with_body(
+                    "https://a.com/runtime.1a.js",
+                    r#"r.u=e=>e+"."+{42:"9f8e"}[e]+".js";"#,
+                ),
+        );
+        let discovery = ParsesmClient::builder()
+            .with_fetcher(fetcher.clone())
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build()
+            .discover("https://a.com/")
+            .await
+            .unwrap();
+        assert_eq!(discovery.frameworks.len(), 1);
+        let angular = &discovery.frameworks[0];
+        assert_eq!(angular.framework, "angular");
+        assert_eq!(angular.version.as_deref(), Some("17.0.8"));
+
+        // the lazy chunk comes from following the runtime, fetched only once
+        let scripts: Vec<&str> = discovery.scripts.iter().map(|s| s.url.as_str()).collect();
+        assert!(scripts.contains(&"https://a.com/42.9f8e.js"));
+        let runtime_requests = fetcher
+            .requests()
+            .iter()
+            .filter(|r| r.as_str() == "https://a.com/runtime.1a.js")
+            .count();
+        assert_eq!(runtime_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn vite_manifest() {
+        let page = r#"<script type="module" src="/assets/index-1a2b.js"></script>"#;
+        let fetcher = MockFetcher::new()
+            .with_body("https://a.com/", page)
+            .with_body(
+                "https://a.com/.vite/manifest.json",
+                r#"{"index.html":{"file":"assets/index-1a2b.js"},"src/lazy.ts":{"file":"assets/lazy-3c4d.js"}}"#,
+            );
+        let vite = detect(fetcher).await;
+        assert_eq!(vite.framework, "vite");
+        assert_eq!(
+            chunks(&vite),
+            [
+                "https://a.com/assets/index-1a2b.js",
+                "https://a.com/assets/lazy-3c4d.js"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn detectors_stay_in_scope() {
+        let page = r#"<script src="https://b.org/manifest-9a8b.js"></script>"#;
+        let fetcher = Arc::new(
+            MockFetcher::new()
+                .with_body("https://a.com/", page)
+                .with_body(
+                    "https://b.org/manifest-9a8b.js",
+                    r#"window.__remixManifest={"entry":{"module":"/entry.js"},"routes":{}};"#,
+                ),
+        );
+        let discovery = ParsesmClient::builder()
+            .with_fetcher(fetcher.clone())
+            .with_reporter(Arc::new(Reporter::new(EventFormat::Json)))
+            .build()
+            .discover("https://a.com/")
+            .await
+            .unwrap();
+        assert!(discovery.frameworks.is_empty());
+        assert_eq!(fetcher.requests(), ["https://a.com/"]);
+    }
+
+    #[tokio::test]
+    async fn detector_errors_are_kept() {
+        let page = r#"<script src="/assets/manifest-9a8b.js"></script>"#;
+        let manifest = Url::parse("https://a.com/assets/manifest-9a8b.js").unwrap();
+        let fetcher = MockFetcher::new()
+            .with_body("https://a.com/", page)
+            .with_response(manifest.as_str(), Response::new(manifest.clone(), 500, ""));
+        let discovery = discover(fetcher).await;
+        assert!(discovery.frameworks.is_empty());
+        assert_eq!(discovery.errors.len(), 1);
+        assert_eq!(discovery.errors[0].status(), Some(500));
+    }
+
+    #[test]
+    fn finds_references_as_written() {
+        let body =
+            r#"<link href="/_nuxt/a.js"> import('/_nuxt/b.mjs') "/_nuxt/style.css" x=/_nuxt/c.js"#;
+        assert_eq!(
+            find_references(body, "/_nuxt/"),
+            ["/_nuxt/a.js", "/_nuxt/b.mjs", "/_nuxt/c.js"]
+        );
+    }
+
+    #[test]
+    fn references_after_multibyte_whitespace() {
+        let body = "<p>bundle\u{a0}_app/immutable/entry/start.abc.js</p>";
+        assert_eq!(
+            find_references(body, "_app/immutable/"),
+            ["_app/immutable/entry/start.abc.js"]
+        );
+    }
+}
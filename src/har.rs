@@ -0,0 +1,193 @@
+//! Reading browser sessions captured as HAR files, as exported by the
+//! devtools of every major browser and by intercepting proxies.
+
+use std::path::Path;
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, LOCATION};
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::ParsesmError;
+use crate::fetch::Headers;
+use crate::replay::Capture;
+
+#[derive(Deserialize)]
+struct HarFile {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Deserialize)]
+struct HarRequest {
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    content: HarContent,
+    #[serde(default, rename = "redirectURL")]
+    redirect_url: String,
+}
+
+#[derive(Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarContent {
+    #[serde(default)]
+    mime_type: String,
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+/// Reads a HAR file.
+pub fn read_har(path: &Path) -> Result<Capture, ParsesmError> {
+    let body = std::fs::read(path).map_err(|source| ParsesmError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    parse_har(&body).map_err(|source| ParsesmError::Json {
+        url: path.display().to_string(),
+        source,
+    })
+}
+
+/// Parses the json of a HAR capture. Entries with invalid urls are skipped.
+pub fn parse_har(body: &[u8]) -> Result<Capture, serde_json::Error> {
+    let file: HarFile = serde_json::from_slice(body)?;
+
+    let mut capture = Capture::default();
+    for entry in file.log.entries {
+        let url = match Url::parse(&entry.request.url) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        let response = entry.response;
+        let mut headers = HeaderMap::new();
+        for header in &response.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(header.name.as_bytes()),
+                HeaderValue::from_str(&header.value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        // some tools only record these outside the headers
+        let recorded = [
+            (LOCATION, &response.redirect_url),
+            (CONTENT_TYPE, &response.content.mime_type),
+        ];
+        for (name, value) in recorded {
+            if !value.is_empty() && !headers.contains_key(&name) {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        let body = decode_content(response.content.text, response.content.encoding);
+        capture.insert(url, response.status, Headers(headers), body);
+    }
+
+    Ok(capture)
+}
+
+fn decode_content(text: Option<String>, encoding: Option<String>) -> Option<String> {
+    let text = text?;
+    match encoding.as_deref() {
+        Some("base64") => base64::decode(text.trim())
+            .ok()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+        _ => Some(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::{Fetcher, Response};
+    use crate::replay::ReplayFetcher;
+
+    const HAR: &str = r#"{"log": {"entries": [
+        {"request": {"url": "https://a.com/"},
+         "response": {"status": 301, "headers": [{"name": "Location", "value": "/home"}], "content": {}}},
+        {"request": {"url": "https://a.com/home"},
+         "response": {"status": 200, "content": {"mimeType": "text/html", "text": "<p>home</p>"}}},
+        {"request": {"url": "https://a.com/app.js"},
+         "response": {"status": 500, "content": {"mimeType": "application/javascript", "text": ""}}},
+        {"request": {"url": "https://a.com/app.js"},
+         "response": {"status": 200, "headers": [{"name": "SourceMap", "value": "app.js.map"}],
+                      "content": {"mimeType": "application/javascript", "text": "Y29uc29sZS5sb2coMSk=", "encoding": "base64"}}},
+        {"request": {"url": "https://a.com/chunk.js"},
+         "response": {"status": 200, "content": {"text": null}}},
+        {"request": {"url": "not a url"},
+         "response": {"status": 200, "content": {}}}
+    ]}}"#;
+
+    async fn get(fetcher: &dyn Fetcher, url: &str) -> Response {
+        fetcher.get(&Url::parse(url).unwrap()).await.unwrap()
+    }
+
+    #[test]
+    fn lists_page_and_scripts() {
+        let capture = parse_har(HAR.as_bytes()).unwrap();
+        assert_eq!(capture.len(), 4);
+        assert_eq!(capture.page_url().unwrap().as_str(), "https://a.com/home");
+        let scripts: Vec<String> = capture
+            .script_urls()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(scripts, ["https://a.com/app.js", "https://a.com/chunk.js"]);
+    }
+
+    #[tokio::test]
+    async fn replays_captured_responses() {
+        let fetcher = ReplayFetcher::new(parse_har(HAR.as_bytes()).unwrap());
+        let page = get(&fetcher, "https://a.com/").await;
+        assert_eq!(page.final_url.as_str(), "https://a.com/home");
+        assert_eq!(page.body, "<p>home</p>");
+
+        let script = get(&fetcher, "https://a.com/app.js#top").await;
+        assert_eq!(script.status, 200);
+        assert_eq!(script.body, "console.log(1)");
+        assert_eq!(script.headers.get("sourcemap"), Some("app.js.map"));
+
+        // captured without a body
+        assert_eq!(get(&fetcher, "https://a.com/chunk.js").await.status, 404);
+    }
+
+    #[tokio::test]
+    async fn follows_redirect_url_without_location() {
+        let har = r#"{"log": {"entries": [
+            {"request": {"url": "https://a.com/"},
+             "response": {"status": 302, "redirectURL": "/home", "content": {}}},
+            {"request": {"url": "https://a.com/home"},
+             "response": {"status": 200, "content": {"mimeType": "text/html", "text": "<p>home</p>"}}}
+        ]}}"#;
+        let fetcher = ReplayFetcher::new(parse_har(har.as_bytes()).unwrap());
+        let page = get(&fetcher, "https://a.com/").await;
+        assert_eq!(page.final_url.as_str(), "https://a.com/home");
+        assert_eq!(page.body, "<p>home</p>");
+    }
+}
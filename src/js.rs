@@ -0,0 +1,138 @@
+//! Just enough scanning of javascript to read the literal tables bundlers
+//! emit, without executing or fully parsing anything.
+
+/// Parses a complete single or double quoted string literal.
+pub(crate) fn parse_string(s: &str) -> Option<String> {
+    let quote = s.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let inner = s.strip_prefix(quote)?.strip_suffix(quote)?;
+
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'x' => out.push(hex_char(&mut chars, 2)?),
+                'u' => out.push(hex_char(&mut chars, 4)?),
+                c => out.push(c),
+            },
+            c if c == quote => return None,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+fn hex_char(chars: &mut std::str::Chars, len: usize) -> Option<char> {
+    let digits: String = chars.take(len).collect();
+    if digits.len() != len {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(&digits, 16).ok()?)
+}
+
+/// Removes parentheses wrapping the whole of `s`.
+pub(crate) fn strip_parens(mut s: &str) -> &str {
+    while let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        if expression_end(inner) < inner.len() {
+            break;
+        }
+        s = inner.trim();
+    }
+    s
+}
+
+/// Returns the length of the expression `s` starts with: up to the first
+/// `,` or `;` or the first closing bracket that was not opened, outside of
+/// strings and brackets.
+pub(crate) fn expression_end(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'"' | b'\'' | b'`' => idx = skip_string(bytes, idx),
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => return idx,
+            b')' | b']' | b'}' => depth -= 1,
+            b',' | b';' if depth == 0 => return idx,
+            _ => {}
+        }
+        idx += 1;
+    }
+    bytes.len()
+}
+
+/// Returns the length of the bracketed contents `s` starts with, up to the
+/// first closing bracket that was not opened, outside of strings.
+pub(crate) fn bracket_end(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'"' | b'\'' | b'`' => idx = skip_string(bytes, idx),
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => return idx,
+            b')' | b']' | b'}' => depth -= 1,
+            _ => {}
+        }
+        idx += 1;
+    }
+    bytes.len()
+}
+
+/// Splits `s` at every `sep` outside of strings and brackets.
+pub(crate) fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let bytes = s.as_bytes();
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'"' | b'\'' | b'`' => idx = skip_string(bytes, idx),
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth = depth.saturating_sub(1),
+            b if depth == 0 && b == sep as u8 => {
+                parts.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Returns the index of the quote closing the string opened at `start`.
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut idx = start + 1;
+    while idx < bytes.len() && bytes[idx] != quote {
+        if bytes[idx] == b'\\' {
+            idx += 1;
+        }
+        idx += 1;
+    }
+    idx.min(bytes.len())
+}
+
+pub(crate) fn strip_keyword<'a>(s: &'a str, keyword: &str) -> Option<&'a str> {
+    s.strip_prefix(keyword)
+        .filter(|rest| !rest.starts_with(is_ident_char))
+}
+
+pub(crate) fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Returns the expression assigned to `name`, e.g. `self.__BUILD_MANIFEST`.
+pub(crate) fn assigned_value<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = body.split_once(name)?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    Some(&rest[..expression_end(rest)])
+}
@@ -0,0 +1,75 @@
+//! Recovers the original sources of a website from its published sourcemaps.
+//!
+//! [`ParsesmClient`] fetches a page, follows the scripts it references, finds
+//! their sourcemaps and writes every source it can recover under an output
+//! directory, together with a [`manifest`] of what was found.
+//! [`ParsesmClient::extract_local`] does the same for bundles and maps that
+//! are already on disk.
+//!
+//! ```no_run
+//! # async fn run() {
+//! let client = parsesm::ParsesmClient::builder()
+//!     .with_out_dir("out")
+//!     .build();
+//! let report = client.extract_map("https://example.com/").await;
+//! println!("recovered {} sources", report.written);
+//! # }
+//! ```
+//!
+//! The building blocks are public as well: [`discovery`] finds scripts and map
+//! urls, [`load_from_reader`] decodes maps and [`SourceWriter`] stores sources
+//! safely in a [`Sink`], a directory, archive or memory. A [`Fetcher`]
+//! decides where pages, scripts and maps come from, so extraction also runs
+//! against mirrors on disk, canned responses or HAR and WARC captures
+//! replayed by a [`ReplayFetcher`].
+
+#![warn(missing_docs)]
+
+mod angular;
+mod client;
+pub mod data_url;
+pub mod discovery;
+mod error;
+mod esm;
+pub mod events;
+mod fetch;
+pub mod framework;
+mod har;
+mod js;
+pub mod limiter;
+mod literals;
+pub mod manifest;
+mod nextjs;
+pub mod normalize;
+mod nuxt;
+mod offline;
+mod paths;
+mod remix;
+mod replay;
+pub mod report;
+mod scope;
+mod sink;
+mod sveltekit;
+mod vite;
+mod warc;
+mod webpack;
+mod writer;
+
+pub use crate::client::{
+    load_from_reader, Discovery, ParsesmClient, ParsesmClientBuilder, ScriptEntry,
+    DEFAULT_FOLLOW_DEPTH,
+};
+pub use crate::error::{Level, ParsesmError};
+pub use crate::fetch::{Fetcher, Headers, HttpFetcher, LocalFetcher, MockFetcher, Response};
+pub use crate::framework::{Detection, FrameworkDetector};
+pub use crate::har::read_har;
+pub use crate::offline::discover_local;
+pub use crate::replay::{Capture, ReplayFetcher};
+pub use crate::report::ExtractReport;
+pub use crate::scope::Scope;
+pub use crate::sink::{
+    ArchiveFormat, DirSink, MemorySink, OutputDir, Sink, SinkFactory, TarGzSink, ZipSink,
+};
+pub use crate::warc::{read_warc, WarcWriter};
+pub use crate::webpack::WebpackRuntime;
+pub use crate::writer::{ConflictPolicy, SourceWriter, WriteOutcome, WriteStats};
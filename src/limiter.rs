@@ -0,0 +1,157 @@
+//! Bounding of concurrent and per-second requests.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Longest wait between the start of two requests, however low the rate.
+pub const MAX_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Limits applied to the requests made by a client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Limits {
+    /// Requests in flight across all hosts.
+    pub concurrency: usize,
+    /// Requests in flight to a single host.
+    pub per_host: usize,
+    /// Requests started per second across all hosts, unlimited when `None`.
+    /// Rates below one per [`MAX_INTERVAL`] are raised to it.
+    pub requests_per_second: Option<f64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            per_host: 6,
+            requests_per_second: None,
+        }
+    }
+}
+
+/// Held for the duration of a request, releases its slots when dropped.
+pub struct RequestPermit {
+    _global: OwnedSemaphorePermit,
+    _host: OwnedSemaphorePermit,
+}
+
+/// Hands out [`RequestPermit`]s according to a set of [`Limits`].
+pub struct RequestLimiter {
+    limits: Limits,
+    global: Arc<Semaphore>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    next_start: tokio::sync::Mutex<Instant>,
+}
+
+impl RequestLimiter {
+    /// Creates a limiter enforcing `limits`.
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            global: Arc::new(Semaphore::new(limits.concurrency.max(1))),
+            hosts: Mutex::new(HashMap::new()),
+            next_start: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until a request to `host` may start.
+    pub async fn acquire(&self, host: &str) -> RequestPermit {
+        let host_semaphore = self
+            .hosts
+            .lock()
+            .expect("host limiter poisoned")
+            .entry(host.to_owned())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limits.per_host.max(1))))
+            .clone();
+
+        // semaphores are never closed so acquiring cannot fail
+        let host_permit = host_semaphore
+            .acquire_owned()
+            .await
+            .expect("semaphore closed");
+        let global_permit = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore closed");
+
+        if let Some(rps) = self.limits.requests_per_second.filter(|r| *r > 0.0) {
+            let interval = Duration::try_from_secs_f64(1.0 / rps)
+                .map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL));
+            let start = {
+                let mut next_start = self.next_start.lock().await;
+                let start = (*next_start).max(Instant::now());
+                *next_start = start.checked_add(interval).unwrap_or(start);
+                start
+            };
+            tokio::time::sleep_until(start).await;
+        }
+
+        RequestPermit {
+            _global: global_permit,
+            _host: host_permit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(concurrency: usize, per_host: usize, rate: Option<f64>) -> RequestLimiter {
+        RequestLimiter::new(Limits {
+            concurrency,
+            per_host,
+            requests_per_second: rate,
+        })
+    }
+
+    /// Whether a request to `host` could start within a few milliseconds.
+    async fn starts(limiter: &RequestLimiter, host: &str) -> bool {
+        tokio::time::timeout(Duration::from_millis(20), limiter.acquire(host))
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn limits_requests_per_host() {
+        let limiter = limiter(16, 1, None);
+        let permit = limiter.acquire("a.com").await;
+        assert!(!starts(&limiter, "a.com").await);
+        assert!(starts(&limiter, "b.com").await);
+        drop(permit);
+        assert!(starts(&limiter, "a.com").await);
+    }
+
+    #[tokio::test]
+    async fn limits_requests_across_hosts() {
+        let limiter = limiter(1, 6, None);
+        let permit = limiter.acquire("a.com").await;
+        assert!(!starts(&limiter, "b.com").await);
+        drop(permit);
+        assert!(starts(&limiter, "b.com").await);
+    }
+
+    #[tokio::test]
+    async fn spaces_requests_by_rate() {
+        let limiter = limiter(16, 6, Some(20.0));
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("a.com").await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn tiny_rates_wait_at_most_the_max_interval() {
+        let limiter = limiter(16, 6, Some(f64::MIN_POSITIVE));
+        assert!(starts(&limiter, "a.com").await);
+        let next_start = *limiter.next_start.lock().await;
+        assert!(next_start <= Instant::now() + MAX_INTERVAL);
+    }
+}
@@ -0,0 +1,266 @@
+//! Finding the scripts a bundle refers to in its string literals.
+//!
+//! Bundlers write the paths of the chunks they load into the bundle, e.g.
+//! `"static/js/123.abcd1234.chunk.js"` or `import("./x-9f8e.js")`, even when
+//! the runtime that loads them is not one [`find_runtime`] understands. The
+//! bundle is parsed and every string that looks like the path of a script is
+//! kept, so no framework specific knowledge is needed.
+//!
+//! [`find_runtime`]: crate::webpack::find_runtime
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{StringLiteral, TemplateLiteral};
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::{Parser, ParserReturn};
+use oxc_span::SourceType;
+use url::Url;
+
+use crate::esm::{find_specifiers, resolve_imports};
+use crate::js::parse_string;
+
+const SCRIPT_SUFFIXES: [&str; 3] = [".js", ".mjs", ".cjs"];
+
+/// Longest literal considered a path, longer strings are embedded data.
+const MAX_PATH_LEN: usize = 2048;
+
+/// The script paths found in the string literals of a script.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScriptLiterals {
+    /// Paths of scripts, in the order they appear.
+    pub paths: Vec<String>,
+    /// Why the script could not be parsed, the paths were then found by
+    /// scanning its string tokens instead.
+    pub parse_error: Option<String>,
+}
+
+/// Returns the modules a script at `module_url` imports and the string
+/// literals in it that look like the path of a script, parsing it once for
+/// both.
+///
+/// Template literals without substitutions count as strings. The parser
+/// gives up on the whole script at the first syntax error, so scripts that
+/// fail to parse import nothing and are scanned for quoted strings instead,
+/// skipping comments. That scan may be misled by regular expressions
+/// containing quotes.
+pub(crate) fn find_script_references(module_url: &Url, body: &str) -> (Vec<Url>, ScriptLiterals) {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, body, SourceType::unambiguous()).parse();
+    let literals = collect_literals(body, &parsed);
+    let imports = match literals.parse_error {
+        Some(_) => vec![],
+        None => resolve_imports(module_url, find_specifiers(&parsed.program)),
+    };
+    (imports, literals)
+}
+
+fn collect_literals(body: &str, parsed: &ParserReturn<'_>) -> ScriptLiterals {
+    let mut collector = Collector { literals: vec![] };
+    let parse_error = match parsed.errors.first() {
+        Some(error) => {
+            for string in scan_strings(body) {
+                collector.add(&string);
+            }
+            Some(error.to_string())
+        }
+        None if parsed.panicked => {
+            for string in scan_strings(body) {
+                collector.add(&string);
+            }
+            Some("the parser gave up".to_owned())
+        }
+        None => {
+            collector.visit_program(&parsed.program);
+            None
+        }
+    };
+    ScriptLiterals {
+        paths: collector.literals,
+        parse_error,
+    }
+}
+
+/// Resolves literals found in the script at `script_url`.
+///
+/// Paths starting with `./` or `../` are module specifiers relative to the
+/// script. Any other path is relative to `public_base`, the public path of the
+/// bundle or else the page, as bundlers join chunk names to it.
+pub fn resolve_literals(literals: &[String], script_url: &Url, public_base: &Url) -> Vec<Url> {
+    let mut urls: Vec<Url> = vec![];
+    for literal in literals {
+        let base = if literal.starts_with("./") || literal.starts_with("../") {
+            script_url
+        } else {
+            public_base
+        };
+        if let Ok(url) = base.join(literal) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+struct Collector {
+    literals: Vec<String>,
+}
+
+impl Collector {
+    fn add(&mut self, value: &str) {
+        if is_script_path(value) && !self.literals.iter().any(|l| l == value) {
+            self.literals.push(value.to_owned());
+        }
+    }
+}
+
+impl<'a> Visit<'a> for Collector {
+    fn visit_string_literal(&mut self, it: &StringLiteral<'a>) {
+        self.add(&it.value);
+    }
+
+    fn visit_template_literal(&mut self, it: &TemplateLiteral<'a>) {
+        if let ([quasi], []) = (&it.quasis[..], &it.expressions[..]) {
+            if let Some(cooked) = &quasi.value.cooked {
+                self.add(cooked);
+            }
+        }
+        walk::walk_template_literal(self, it);
+    }
+}
+
+/// Returns the contents of the quoted strings in `body` outside of comments,
+/// template literals only when they have no substitutions.
+fn scan_strings(body: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut rest = body;
+    while let Some(idx) = rest.find(['"', '\'', '`', '/']) {
+        let token = &rest[idx..];
+        let quote = token.as_bytes()[0];
+        if quote == b'/' {
+            rest = if token.starts_with("//") {
+                token.find('\n').map_or("", |end| &token[end..])
+            } else if token.starts_with("/*") {
+                token.find("*/").map_or("", |end| &token[end + 2..])
+            } else {
+                &token[1..]
+            };
+            continue;
+        }
+
+        // find the closing quote, skipping escaped characters
+        let mut end = None;
+        let mut escaped = false;
+        for (i, b) in token.bytes().enumerate().skip(1) {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'\n' if quote != b'`' => break,
+                _ if b == quote => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                rest = &token[1..];
+                continue;
+            }
+        };
+        let literal = &token[..=end];
+        let string = if quote == b'`' {
+            let inner = &literal[1..end];
+            (!inner.contains("${") && !inner.contains('\\')).then(|| inner.to_owned())
+        } else {
+            parse_string(literal)
+        };
+        strings.extend(string);
+        rest = &token[end + 1..];
+    }
+    strings
+}
+
+/// Whether a string is the path or url of a script that can be fetched.
+///
+/// Bare file names such as `"jquery.js"` are usually names rather than
+/// paths, so a path needs a directory or a digit, as hashed chunk names
+/// have. Paths into `node_modules` only exist on the machine that built the
+/// bundle.
+fn is_script_path(value: &str) -> bool {
+    if value.is_empty() || value.len() > MAX_PATH_LEN {
+        return false;
+    }
+    let path = value.split(['?', '#']).next().unwrap_or(value);
+    if !SCRIPT_SUFFIXES.iter().any(|s| path.ends_with(s)) {
+        return false;
+    }
+    let has_scheme = path
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.contains('/'));
+    if has_scheme && !path.starts_with("http://") && !path.starts_with("https://") {
+        return false;
+    }
+    if path.contains("node_modules/") {
+        return false;
+    }
+    let valid = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-._~!$&'()+,;=:@%/?#".contains(c));
+    let name = path.rsplit('/').next().unwrap_or(path);
+    valid && (path.contains('/') || name.contains(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_script_literals(body: &str) -> ScriptLiterals {
+        let url = Url::parse("https://a.com/").unwrap();
+        find_script_references(&url, body).1
+    }
+
+    #[test]
+    fn finds_script_paths() {
+        let body = r#"load("static/js/123.abcd1234.chunk.js"); import(`./x-9f8e.js`);
+            var a = "jquery.js", b = "node_modules/a/b.js", c = "text", d = "https://cdn.com/v2/lib.js";"#;
+        let literals = find_script_literals(body);
+        assert_eq!(literals.parse_error, None);
+        assert_eq!(
+            literals.paths,
+            [
+                "static/js/123.abcd1234.chunk.js",
+                "./x-9f8e.js",
+                "https://cdn.com/v2/lib.js"
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_scripts_that_fail_to_parse() {
+        let body = "var a='static/js/1.js'; /* 'static/js/no.js' */ var b=\"static/js/2.js\"; }";
+        let literals = find_script_literals(body);
+        assert!(literals.parse_error.is_some());
+        assert_eq!(literals.paths, ["static/js/1.js", "static/js/2.js"]);
+    }
+
+    #[test]
+    fn resolves_against_script_or_public_path() {
+        let literals = ["./a.js".to_owned(), "static/js/b.js".to_owned()];
+        let script = Url::parse("https://a.com/assets/main.js").unwrap();
+        let public = Url::parse("https://cdn.com/app/").unwrap();
+        let urls: Vec<String> = resolve_literals(&literals, &script, &public)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://a.com/assets/a.js",
+                "https://cdn.com/app/static/js/b.js"
+            ]
+        );
+    }
+}
@@ -1,196 +1,449 @@
-use std::env;
-use std::fs;
-use std::io::Read;
+mod cli;
+
+use std::io::BufRead;
+use std::process::ExitCode;
+use std::sync::Arc;
 
 use ansi_term::Colour;
-use reqwest::{Client, ClientBuilder};
-use sourcemap::{decode, DecodedMap, RewriteOptions, SourceMap};
-
-fn load_from_reader<R: Read>(mut rdr: R) -> Result<SourceMap, sourcemap::Error> {
-    let decoded = decode(&mut rdr);
-    if decoded.is_ok() {
-        match decoded.unwrap() {
-            DecodedMap::Regular(sm) => Ok(sm),
-            DecodedMap::Index(idx) => idx.flatten_and_rewrite(&RewriteOptions {
-                load_local_source_contents: true,
-                ..Default::default()
-            }),
-            e => Err(sourcemap::Error::IncompatibleSourceMap),
+use clap::{CommandFactory, Parser};
+use futures::stream::{self, StreamExt};
+use serde_json::json;
+use sourcemap::SourceMap;
+use url::Url;
+
+use parsesm::data_url::{decode_data_url, is_data_url};
+use parsesm::events::{display_url, Event, Reporter};
+use parsesm::report::{batch_exit_code, exit_code, print_summary, ExtractReport, Outcome};
+use parsesm::{
+    load_from_reader, read_har, read_warc, Capture, ParsesmClient, ParsesmError, ReplayFetcher,
+};
+
+use crate::cli::{
+    AnalyzeArgs, BatchArgs, Cli, Command, ExtractArgs, LookupArgs, OfflineArgs, OutputFormat,
+    ReplayArgs, ValidateArgs,
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut cli = Cli::parse();
+    let format = cli.output_format;
+
+    if let Some(network) = cli.command.network_mut() {
+        if let Err(e) = network.open_recorder() {
+            eprintln!(
+                "{} {}",
+                Colour::Red.paint("failed to create warc:"),
+                e.chain()
+            );
+            return ExitCode::from(exit_code::FAILURE);
         }
-    } else {
-        Err(sourcemap::Error::IncompatibleSourceMap)
     }
+
+    let code = match cli.command {
+        Command::Extract(args) => extract(args, format).await,
+        Command::Batch(args) => batch(args, format).await,
+        Command::Offline(args) => offline(args, format).await,
+        Command::Har(args) => replay(read_har, args, format).await,
+        Command::Warc(args) => replay(read_warc, args, format).await,
+        Command::Analyze(args) => analyze(args, format).await,
+        Command::Lookup(args) => lookup(args, format).await,
+        Command::Validate(args) => validate(args, format).await,
+        Command::Completions { shell } => {
+            let mut cmd = Cli::command();
+            clap_complete::generate(shell, &mut cmd, "parsesm", &mut std::io::stdout());
+            exit_code::OK
+        }
+    };
+
+    ExitCode::from(code)
 }
 
-fn write_contents(host: &str, path: &str, contents: &str) -> std::io::Result<()> {
-    use std::io::Write;
-    use std::path::Path;
+async fn extract(args: ExtractArgs, format: OutputFormat) -> u8 {
+    let reporter = Arc::new(Reporter::new(format.event_format()));
+    let client = args
+        .network
+        .client()
+        .with_reporter(reporter.clone())
+        .with_out_dir(&args.output.out_dir)
+        .with_archive(args.output.archive)
+        .with_conflict_policy(args.output.on_conflict)
+        .build();
 
-    let mut host = host.trim_start_matches("http://");
-    host = host.trim_start_matches("https://");
-    let mut trimmed = path.trim_start_matches("webpack:///");
-    trimmed = trimmed.trim_start_matches("./");
-    let parsed = Path::new(trimmed);
+    let report = client.extract_map(&args.url).await;
+    if format == OutputFormat::Json {
+        print_json(&json!({
+            "report": report,
+            "events": reporter.take_events(),
+        }));
+    }
 
-    // if its a module include the module otherwise write it to out dir
-    let out_dir = if let Some(module) = parsed.parent() {
-        format!("./out/{}/{}", host, module.to_str().unwrap())
-    } else {
-        format!("./out/{}", host)
+    report.outcome().exit_code()
+}
+
+async fn batch(args: BatchArgs, format: OutputFormat) -> u8 {
+    let lines = match read_targets(&args) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("{} {}", Colour::Red.paint("failed to read targets:"), e);
+            return exit_code::FAILURE;
+        }
     };
-    let file_name = parsed.file_name().expect("failed to get file name");
-    // creating dir for source if it doesnt exist
-    std::fs::create_dir_all(out_dir.clone())?;
-
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(format!(
-            "{}/{}", // ./out/module/file || ./out/file
-            out_dir,
-            file_name.to_str().expect("failed to get str frmo filename")
-        ))?;
-
-    file.write_all(contents.as_bytes())?;
-
-    eprintln!(
-        "found original source for module {} and file {} of size {}",
-        out_dir,
-        file_name.to_str().unwrap(),
-        contents.len()
+
+    let reporter = Arc::new(Reporter::new(format.event_format()));
+    let client = Arc::new(
+        args.network
+            .client()
+            .with_reporter(reporter.clone())
+            .with_out_dir(&args.output.out_dir)
+            .with_archive(args.output.archive)
+            .with_conflict_policy(args.output.on_conflict)
+            .build(),
     );
-    Ok(())
+
+    let expand_hosts = args.expand_hosts;
+    let reports: Vec<ExtractReport> = stream::iter(lines)
+        .map(|line| {
+            let client = client.clone();
+            let reporter = reporter.clone();
+            async move {
+                // each target runs in its own task so a panic only loses that target
+                let task = tokio::spawn({
+                    let line = line.clone();
+                    async move { extract_target(&client, &line, expand_hosts).await }
+                });
+                task.await.unwrap_or_else(|e| {
+                    let mut report = ExtractReport::new(&line);
+                    report.error = Some(format!("extraction panicked: {}", e));
+                    reporter.emit(Event::Finished(report.clone()));
+                    report
+                })
+            }
+        })
+        .buffered(args.parallel_targets.max(1))
+        .collect()
+        .await;
+
+    match format {
+        OutputFormat::Json => print_json(&json!({
+            "reports": reports,
+            "events": reporter.take_events(),
+        })),
+        OutputFormat::Ndjson => {}
+        OutputFormat::Text => print_summary(&reports),
+    }
+
+    batch_exit_code(&reports)
 }
 
-struct ParsesmClient {
-    inner: reqwest::Client,
+async fn offline(args: OfflineArgs, format: OutputFormat) -> u8 {
+    let reporter = Arc::new(Reporter::new(format.event_format()));
+    let client = ParsesmClient::builder()
+        .with_reporter(reporter.clone())
+        .with_out_dir(&args.output.out_dir)
+        .with_archive(args.output.archive)
+        .with_conflict_policy(args.output.on_conflict)
+        .build();
+
+    let mut reports = vec![];
+    for path in &args.paths {
+        reports.push(client.extract_local(path).await);
+    }
+
+    match format {
+        OutputFormat::Json => print_json(&json!({
+            "reports": reports,
+            "events": reporter.take_events(),
+        })),
+        OutputFormat::Text if reports.len() > 1 => print_summary(&reports),
+        _ => {}
+    }
+
+    batch_exit_code(&reports)
 }
 
-impl ParsesmClient {
-    pub fn new() -> Self {
-        use std::time::Duration;
-
-        let client = Client::builder()
-            .use_native_tls()
-            .danger_accept_invalid_hostnames(true)
-            .danger_accept_invalid_certs(true)
-            .pool_max_idle_per_host(5)
-            .pool_idle_timeout(Duration::from_secs(15))
-            .build()
-            .expect("failed to build client");
-
-        Self { inner: client }
-    }
-
-    pub async fn extract_map(&self, host: &str) -> std::io::Result<()> {
-        use bytes::{Buf, Bytes};
-
-        eprintln!(
-            "attempting to find sourcemaps for {}",
-            Colour::White.bold().paint(host)
-        );
-        let resp = self.inner.get(host).send().await;
-        match resp {
-            Ok(r) => {
-                if !r.status().is_success() {
-                    return Ok(());
-                }
-
-                let body = r.text().await.expect("failed to get body");
-                let relative_scripts = Self::find_scripts(&host, &body);
-                // needs to be string for colour
-                let relative_scripts_len = relative_scripts.len().to_string();
-                eprintln!(
-                    "found {} relative javascript files",
-                    Colour::White.bold().paint(&relative_scripts_len)
-                );
+async fn replay(
+    read: fn(&std::path::Path) -> Result<Capture, ParsesmError>,
+    args: ReplayArgs,
+    format: OutputFormat,
+) -> u8 {
+    let capture = match read(&args.capture) {
+        Ok(capture) => capture,
+        Err(e) => {
+            eprintln!(
+                "{} {}",
+                Colour::Red.paint("failed to read capture:"),
+                e.chain()
+            );
+            return exit_code::FAILURE;
+        }
+    };
+    let page = match args
+        .page
+        .clone()
+        .or_else(|| capture.page_url().map(Url::to_string))
+    {
+        Some(page) => page,
+        None => {
+            eprintln!(
+                "{} no html page in the capture, pass --page",
+                Colour::Red.paint("error:")
+            );
+            return exit_code::FAILURE;
+        }
+    };
+    let scripts = capture.script_urls();
+
+    let fallback = args.fetch_missing.then(|| args.network.fetcher());
+    let reporter = Arc::new(Reporter::new(format.event_format()));
+    let client = args
+        .network
+        .client()
+        .with_fetcher(Arc::new(
+            ReplayFetcher::new(capture).with_fallback(fallback),
+        ))
+        .with_reporter(reporter.clone())
+        .with_out_dir(&args.output.out_dir)
+        .with_archive(args.output.archive)
+        .with_conflict_policy(args.output.on_conflict)
+        .build();
+
+    let report = client.extract_with_scripts(&page, &scripts).await;
+    if format == OutputFormat::Json {
+        print_json(&json!({
+            "report": report,
+            "events": reporter.take_events(),
+        }));
+    }
+
+    report.outcome().exit_code()
+}
+
+/// Reads the non empty, non comment lines of the batch input.
+fn read_targets(args: &BatchArgs) -> std::io::Result<Vec<String>> {
+    let reader: Box<dyn BufRead> = match &args.input {
+        Some(path) if path.as_os_str() != "-" => {
+            Box::new(std::io::BufReader::new(std::fs::File::open(path)?))
+        }
+        _ => Box::new(std::io::stdin().lock()),
+    };
+
+    let mut targets = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            targets.push(line.to_owned());
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Extracts a single batch target, expanding bare hostnames when allowed.
+async fn extract_target(client: &ParsesmClient, target: &str, expand_hosts: bool) -> ExtractReport {
+    if target.contains("://") {
+        return client.extract_map(target).await;
+    }
+
+    if !expand_hosts {
+        let mut report = ExtractReport::new(target);
+        report.error = Some("not a url, pass --expand-hosts to accept hostnames".to_owned());
+        client.reporter().emit(Event::Finished(report.clone()));
+        return report;
+    }
+
+    let report = client.extract_map(&format!("https://{}/", target)).await;
+    if report.outcome() != Outcome::NetworkFailure {
+        return report;
+    }
+    client.extract_map(&format!("http://{}/", target)).await
+}
+
+async fn analyze(args: AnalyzeArgs, format: OutputFormat) -> u8 {
+    let client = args
+        .network
+        .client()
+        .with_reporter(Arc::new(Reporter::new(format.event_format())))
+        .build();
+    let discovery = match client.discover(&args.url).await {
+        Ok(discovery) => discovery,
+        Err(e) => {
+            eprintln!("{} {}", Colour::Red.paint("failed to fetch page:"), e);
+            return Outcome::NetworkFailure.exit_code();
+        }
+    };
+
+    let scripts: Vec<_> = discovery
+        .scripts
+        .iter()
+        .map(|script| {
+            let sm = script
+                .map_body
+                .as_ref()
+                .and_then(|body| load_from_reader(body.as_bytes()).ok());
+            json!({
+                "url": script.url,
+                "status": script.status,
+                "map_url": script.map_url.as_deref().map(display_url),
+                "map_status": script.map_status,
+                "inline_map": script.inline_map,
+                "valid": sm.is_some(),
+                "sources": sm.as_ref().map(|sm| sm.get_source_count()).unwrap_or(0),
+                "sources_with_content": sm.as_ref().map(sources_with_content).unwrap_or(0),
+            })
+        })
+        .collect();
 
-                let js_maps = self.fetch_map_files(relative_scripts).await?;
-                if js_maps.len() == 0 {
-                    println!(
-                        "no sourcemaps found for {} javascript files. exiting",
-                        Colour::White.bold().paint(&relative_scripts_len)
-                    );
-                    return Ok(());
-                }
-
-                eprintln!(
-                    "found {}/{} sourcemaps for javascript files",
-                    js_maps.len(),
-                    Colour::White.bold().paint(&relative_scripts_len)
+    match format {
+        OutputFormat::Text => {
+            for script in scripts.iter().filter(|s| !s["map_url"].is_null()) {
+                println!(
+                    "{} {} sources, {} with content",
+                    script["map_url"].as_str().unwrap_or_default(),
+                    script["sources"],
+                    script["sources_with_content"]
                 );
-                js_maps
-                    .into_iter()
-                    .filter_map(|m| {
-                        let buf = Bytes::from(m.1);
-                        load_from_reader(buf.reader()).ok()
-                    })
-                    .for_each(|sm| {
-                        sm.sources()
-                            .zip(sm.source_contents())
-                            .filter_map(|s| if s.1.is_some() { Some(s) } else { None })
-                            .for_each(|s| {
-                                // unwrap is okay because we verified its Some above
-                                if let Err(e) = write_contents(host, s.0, s.1.unwrap()) {
-                                    //todo: log error
-                                }
-                            });
-                    })
             }
-            _ => {}
         }
+        _ => print_output(
+            format,
+            &json!({
+                "page_url": discovery.page_url.as_str(),
+                "page_status": discovery.page_status,
+                "scripts": scripts,
+            }),
+        ),
+    }
 
-        Ok(())
-    }
-
-    pub async fn fetch_map_files(
-        &self,
-        scripts: Vec<String>,
-    ) -> std::io::Result<Vec<(String, String)>> {
-        let mut bodies = vec![];
-        for s in scripts {
-            match self.inner.get(s.clone()).send().await {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        let body = resp.text().await.expect("failed to get body");
-                        bodies.push((s, body));
-                    }
-                }
-                Err(e) => {
-                    //todo: log error
-                }
-            }
+    if discovery.maps().next().is_none() {
+        Outcome::NoMaps.exit_code()
+    } else {
+        Outcome::Complete.exit_code()
+    }
+}
+
+async fn lookup(args: LookupArgs, format: OutputFormat) -> u8 {
+    let client = args.network.client().build();
+    let sm = match read_map(&client, &args.map).await {
+        Ok(sm) => sm,
+        Err(e) => {
+            eprintln!("{} {}", Colour::Red.paint("failed to load map:"), e);
+            return exit_code::FAILURE;
         }
+    };
+
+    let line = args.line.saturating_sub(1);
+    let column = args.column.saturating_sub(1);
+    let token = match sm.lookup_token(line, column) {
+        Some(token) => token,
+        None => {
+            eprintln!("no mapping for {}:{}", args.line, args.column);
+            return exit_code::FAILURE;
+        }
+    };
 
-        Ok(bodies)
+    let source = token.get_source().unwrap_or("<unknown>");
+    let (src_line, src_col) = (token.get_src_line() + 1, token.get_src_col() + 1);
+    match format {
+        OutputFormat::Text => match token.get_name() {
+            Some(name) => println!("{}:{}:{} {}", source, src_line, src_col, name),
+            None => println!("{}:{}:{}", source, src_line, src_col),
+        },
+        _ => print_output(
+            format,
+            &json!({
+                "source": source,
+                "line": src_line,
+                "column": src_col,
+                "name": token.get_name(),
+            }),
+        ),
     }
 
-    pub fn find_scripts(host: &str, body: &str) -> Vec<String> {
-        use scraper::{Html, Selector};
-        let mut res = vec![];
-        let doc = Html::parse_document(body);
-        let selector = Selector::parse("script").expect("failed to create selector");
+    exit_code::OK
+}
+
+async fn validate(args: ValidateArgs, format: OutputFormat) -> u8 {
+    let client = args.network.client().build();
+    let mut results = vec![];
+    for map in &args.maps {
+        let result = match read_map(&client, map).await {
+            Ok(sm) => json!({
+                "map": map,
+                "valid": true,
+                "sources": sm.get_source_count(),
+                "sources_with_content": sources_with_content(&sm),
+                "tokens": sm.get_token_count(),
+            }),
+            Err(e) => json!({ "map": map, "valid": false, "error": e }),
+        };
 
-        for e in doc.select(&selector) {
-            if let Some(src) = e.value().attr("src") {
-                // relative url are only considered as part of the apps sourcemap for now
-                if src.starts_with("/") {
-                    let src = src.replace(".js", ".js.map");
-                    res.push(format!("{}{}", host, src));
-                } else {
-                    //res.push(src.to_owned());
-                }
+        if format == OutputFormat::Ndjson {
+            print_output(format, &result);
+        } else if format == OutputFormat::Text {
+            if result["valid"] == true {
+                println!(
+                    "{} {}: {} sources, {} with content, {} mappings",
+                    Colour::Green.paint("ok"),
+                    map,
+                    result["sources"],
+                    result["sources_with_content"],
+                    result["tokens"]
+                );
+            } else {
+                println!(
+                    "{} {}: {}",
+                    Colour::Red.paint("invalid"),
+                    map,
+                    result["error"].as_str().unwrap_or_default()
+                );
             }
         }
+        results.push(result);
+    }
 
-        res
+    if format == OutputFormat::Json {
+        print_output(format, &results);
+    }
+
+    if results.iter().all(|r| r["valid"] == true) {
+        exit_code::OK
+    } else {
+        exit_code::FAILURE
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let client = ParsesmClient::new();
-    let args: Vec<_> = env::args().collect();
-    client.extract_map(&args[1]).await;
+/// Loads a sourcemap from an http(s) url, a `data:` url or a local path.
+async fn read_map(client: &ParsesmClient, location: &str) -> Result<SourceMap, String> {
+    let body = if is_data_url(location) {
+        decode_data_url(location).ok_or_else(|| "malformed data url".to_owned())?
+    } else if location.starts_with("http://") || location.starts_with("https://") {
+        client.fetch(location).await.map_err(|e| e.chain())?.body
+    } else {
+        std::fs::read_to_string(location).map_err(|e| e.to_string())?
+    };
+
+    load_from_reader(body.as_bytes()).map_err(|e| e.to_string())
+}
+
+fn sources_with_content(sm: &SourceMap) -> usize {
+    (0..sm.get_source_count())
+        .filter(|idx| sm.get_source_contents(*idx).is_some())
+        .count()
+}
+
+fn print_json<T: serde::Serialize>(value: &T) {
+    print_output(OutputFormat::Json, value)
+}
+
+/// Prints a value as pretty json, or as a single line in ndjson mode.
+fn print_output<T: serde::Serialize>(format: OutputFormat, value: &T) {
+    let json = match format {
+        OutputFormat::Ndjson => serde_json::to_string(value),
+        _ => serde_json::to_string_pretty(value),
+    };
+    match json {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("{} {}", Colour::Red.paint("failed to serialize output:"), e),
+    }
 }
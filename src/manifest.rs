@@ -0,0 +1,102 @@
+//! The `manifest.json` recording where every recovered source came from.
+
+use std::path::Path;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ParsesmError;
+use crate::sink::Sink;
+
+/// File name of the manifest inside a target's output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Returns the hex encoded sha-256 of some bytes.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Everything fetched and written for one target.
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct Manifest {
+    /// The target as given on the command line.
+    pub target: String,
+    /// Url of the page after redirects.
+    pub page_url: Option<String>,
+    /// Status of the page response.
+    pub page_status: Option<u16>,
+    /// Frameworks the page is built with.
+    pub frameworks: Vec<ManifestFramework>,
+    /// Scripts on the page, in document order.
+    pub scripts: Vec<ManifestScript>,
+    /// Sources recovered from the maps of the scripts.
+    pub sources: Vec<ManifestSource>,
+    /// Sources without content that could not be downloaded either.
+    pub unrecovered: Vec<String>,
+}
+
+/// A framework detected on the page.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct ManifestFramework {
+    /// Name of the framework.
+    pub name: String,
+    /// Version of the framework, if the page gives it away.
+    pub version: Option<String>,
+    /// Id of the deployed build.
+    pub build: Option<String>,
+    /// Routes of the app the framework lists.
+    pub routes: Vec<String>,
+}
+
+/// A script and the sourcemap found for it.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct ManifestScript {
+    /// Url of the script.
+    pub url: String,
+    /// Status of the script response.
+    pub status: Option<u16>,
+    /// Url of the map, the script url for inline maps.
+    pub map_url: Option<String>,
+    /// Status of the map response.
+    pub map_status: Option<u16>,
+    /// Whether the map was embedded in the script as a `data:` url.
+    pub inline_map: bool,
+    /// Hash of the map as it was served.
+    pub map_sha256: Option<String>,
+}
+
+/// A source recovered from a sourcemap.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct ManifestSource {
+    /// Name of the source in the map.
+    pub name: String,
+    /// Url of the map the source was taken from.
+    pub map_url: String,
+    /// Path relative to the manifest, `None` if the source was not written.
+    pub path: Option<String>,
+    /// Size of the source in bytes.
+    pub size: usize,
+    /// Hash of the source.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Creates an empty manifest for `target`.
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Writes the manifest into `sink`, replacing any previous one.
+    pub fn write(&self, sink: &mut dyn Sink) -> Result<(), ParsesmError> {
+        // serializing plain structs cannot fail
+        let json = serde_json::to_vec_pretty(self).expect("failed to serialize manifest");
+        sink.write(Path::new(MANIFEST_FILE), &json)
+    }
+}
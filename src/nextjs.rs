@@ -0,0 +1,304 @@
+//! Finding every chunk of a Next.js app.
+//!
+//! A page only references the chunks it needs itself. The pages router lists
+//! the chunks of every route in `/_next/static/<buildId>/_buildManifest.js`,
+//! the app router refers to its chunks in the RSC payloads inlined into the
+//! html.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use url::Url;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{fetch_file, Detection, FrameworkDetector, Page};
+use crate::js::{
+    assigned_value, bracket_end, is_ident_char, parse_string, split_top_level, strip_keyword,
+};
+
+const CHUNKS_PREFIX: &str = "static/chunks/";
+
+/// A Next.js build a page belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NextBuild {
+    /// Id of the build, part of the manifest urls.
+    pub build_id: String,
+    /// Url of the `_next/` directory the assets are served from, which
+    /// includes the `assetPrefix` of the app.
+    pub assets_url: Url,
+}
+
+impl NextBuild {
+    /// Url of the `_buildManifest.js` of the build.
+    pub fn build_manifest_url(&self) -> Option<Url> {
+        self.asset_url(&format!("static/{}/_buildManifest.js", self.build_id))
+    }
+
+    /// Url of the `_ssgManifest.js` of the build.
+    pub fn ssg_manifest_url(&self) -> Option<Url> {
+        self.asset_url(&format!("static/{}/_ssgManifest.js", self.build_id))
+    }
+
+    /// Resolves a file listed in a manifest, e.g. `static/chunks/main.js`.
+    pub fn asset_url(&self, file: &str) -> Option<Url> {
+        self.assets_url.join(file.trim_start_matches('/')).ok()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NextData {
+    build_id: String,
+    #[serde(default)]
+    asset_prefix: String,
+}
+
+/// Finds the build of a Next.js page.
+///
+/// The build id is read from the `__NEXT_DATA__` of pages router apps, the
+/// manifest script urls, or the build id inlined into app router payloads.
+/// Returns `None` for pages that are not served by Next.js.
+pub fn find_build(page_url: &Url, body: &str, scripts: &[Url]) -> Option<NextBuild> {
+    let doc = Html::parse_document(body);
+    let selector = Selector::parse("script#__NEXT_DATA__").expect("failed to create selector");
+    let next_data = doc
+        .select(&selector)
+        .next()
+        .and_then(|e| serde_json::from_str::<NextData>(&e.text().collect::<String>()).ok());
+
+    // scripts below _next/ give away a custom asset prefix
+    let assets_url = match &next_data {
+        Some(data) if !data.asset_prefix.is_empty() => page_url.join(&format!(
+            "{}/_next/",
+            data.asset_prefix.trim_end_matches('/')
+        )),
+        _ => scripts
+            .iter()
+            .find_map(|s| {
+                let idx = s.as_str().find("/_next/static/")?;
+                Url::parse(&s.as_str()[..idx + "/_next/".len()]).ok()
+            })
+            .map_or_else(|| page_url.join("/_next/"), Ok),
+    }
+    .ok()?;
+
+    // other frameworks inline a build id too, only trust it on pages that
+    // load Next.js assets
+    let serves_assets = body.contains("/_next/static/");
+    let build_id = next_data
+        .map(|data| data.build_id)
+        .or_else(|| scripts.iter().find_map(manifest_build_id))
+        .or_else(|| inline_build_id(body).filter(|_| serves_assets))?;
+    if build_id.is_empty() || !build_id.chars().all(is_ident_char_or_dash) {
+        return None;
+    }
+
+    Some(NextBuild {
+        build_id,
+        assets_url,
+    })
+}
+
+/// Returns the build id in the url of a `_buildManifest.js` or
+/// `_ssgManifest.js` script.
+fn manifest_build_id(script: &Url) -> Option<String> {
+    let mut segments = script.path_segments()?.rev();
+    let file = segments.next()?;
+    if file != "_buildManifest.js" && file != "_ssgManifest.js" {
+        return None;
+    }
+    Some(segments.next()?.to_owned())
+}
+
+/// Returns the build id inlined into the page, e.g. in an escaped RSC payload
+/// as `\"buildId\":\"abc\"`.
+fn inline_build_id(body: &str) -> Option<String> {
+    body.match_indices("buildId").find_map(|(idx, _)| {
+        let rest = body[idx + "buildId".len()..].trim_start_matches(['\\', '"', '\'', ':', ' ']);
+        let id: String = rest
+            .chars()
+            .take_while(|c| is_ident_char_or_dash(*c))
+            .collect();
+        (!id.is_empty()).then_some(id)
+    })
+}
+
+/// The routes of a pages router app and the chunks each of them loads.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BuildManifest {
+    /// Chunk files of every route, relative to the `_next/` directory.
+    pub routes: BTreeMap<String, Vec<String>>,
+}
+
+/// Parses a `_buildManifest.js`.
+///
+/// Production builds wrap the manifest in a function taking the shared chunk
+/// names as arguments, those are substituted. Only script files are kept.
+pub fn parse_build_manifest(body: &str) -> Option<BuildManifest> {
+    let value = assigned_value(body, "__BUILD_MANIFEST")?;
+
+    let (object, args) = match strip_keyword(value, "function") {
+        Some(function) => {
+            let params_start = function.find('(')? + 1;
+            let params =
+                &function[params_start..params_start + bracket_end(&function[params_start..])];
+            let rest = function.get(params_start + params.len() + 1..)?;
+            let block = rest.trim_start().strip_prefix('{')?;
+            let block_len = bracket_end(block);
+            let object = strip_keyword(block[..block_len].trim_start(), "return")?.trim_start();
+            let call = block.get(block_len + 1..)?.trim_start().strip_prefix('(')?;
+            let values = split_top_level(&call[..bracket_end(call)], ',');
+
+            let args: HashMap<&str, String> = split_top_level(params, ',')
+                .into_iter()
+                .map(str::trim)
+                .zip(values)
+                .filter_map(|(param, value)| Some((param, parse_string(value.trim())?)))
+                .collect();
+            (object, args)
+        }
+        None => (value, HashMap::new()),
+    };
+
+    let entries = object.strip_prefix('{')?;
+    let entries = &entries[..bracket_end(entries)];
+    let mut manifest = BuildManifest::default();
+    for entry in split_top_level(entries, ',') {
+        let (key, value) = match split_top_level(entry, ':')[..] {
+            [key, value] => (key.trim(), value.trim()),
+            _ => continue,
+        };
+        let route = match parse_string(key) {
+            Some(route) => route,
+            None => key.to_owned(),
+        };
+        let items = match value.strip_prefix('[') {
+            Some(items) if route.starts_with('/') => &items[..bracket_end(items)],
+            _ => continue,
+        };
+
+        let chunks = split_top_level(items, ',')
+            .into_iter()
+            .map(str::trim)
+            .filter_map(|item| parse_string(item).or_else(|| args.get(item).cloned()))
+            .filter(|file| file.ends_with(".js"))
+            .collect();
+        manifest.routes.insert(route, chunks);
+    }
+
+    Some(manifest)
+}
+
+/// Parses the routes of a `_ssgManifest.js`, the pages generated at build
+/// time.
+pub fn parse_ssg_manifest(body: &str) -> Vec<String> {
+    let routes = assigned_value(body, "__SSG_MANIFEST")
+        .and_then(|value| value.strip_prefix("new Set("))
+        .and_then(|set| set.trim_start().strip_prefix('['));
+    match routes {
+        Some(routes) => split_top_level(&routes[..bracket_end(routes)], ',')
+            .into_iter()
+            .filter_map(|route| parse_string(route.trim()))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Returns the chunk files the page refers to outside of script tags, such as
+/// the client components listed in app router payloads.
+pub fn find_app_chunks(body: &str) -> Vec<String> {
+    let mut chunks: Vec<String> = vec![];
+    for (idx, _) in body.match_indices(CHUNKS_PREFIX) {
+        let file: String = body[idx..]
+            .chars()
+            .take_while(|c| !matches!(c, '"' | '\'' | '\\' | '`') && !c.is_whitespace())
+            .collect();
+        if file.ends_with(".js") && !chunks.contains(&file) {
+            chunks.push(file);
+        }
+    }
+    chunks
+}
+
+fn is_ident_char_or_dash(c: char) -> bool {
+    is_ident_char(c) || c == '-'
+}
+
+/// Detects Next.js apps, listing the chunks of every route of the pages
+/// router and those the app router payloads refer to.
+pub struct NextDetector;
+
+#[async_trait]
+impl FrameworkDetector for NextDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        let build = find_build(page.url, page.body, page.scripts)?;
+        let mut detection = Detection::new("next.js").with_build(Some(build.build_id.clone()));
+        detection.add_chunks(
+            find_app_chunks(page.body)
+                .iter()
+                .filter_map(|c| build.asset_url(c)),
+        );
+
+        if let Some(url) = build.build_manifest_url() {
+            if let Some(resp) = fetch_file(page, client, &url, errors).await {
+                let manifest = parse_build_manifest(&resp.body).unwrap_or_default();
+                for (route, chunks) in manifest.routes {
+                    let chunks = chunks.iter().filter_map(|c| build.asset_url(c)).collect();
+                    detection.add_route(route, chunks);
+                }
+            }
+        }
+        // pages generated at build time load the chunks of their route
+        if let Some(url) = build.ssg_manifest_url() {
+            if let Some(resp) = fetch_file(page, client, &url, errors).await {
+                for route in parse_ssg_manifest(&resp.body) {
+                    detection.routes.entry(route).or_default();
+                }
+            }
+        }
+
+        Some(detection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_manifest_with_shared_chunks() {
+        let body = r#"self.__BUILD_MANIFEST=function(s,c){return {__rewrites:{afterFiles:[]},"/":[s,"static/chunks/pages/index-a.js"],"/about":[s,c,"static/chunks/pages/about-b.js"],sortedPages:["/","/about"]}}("static/chunks/shared-s.js","static/css/c.css");self.__BUILD_MANIFEST_CB&&self.__BUILD_MANIFEST_CB();"#;
+        let manifest = parse_build_manifest(body).unwrap();
+        assert_eq!(manifest.routes.keys().collect::<Vec<_>>(), ["/", "/about"]);
+        assert_eq!(
+            manifest.routes["/about"],
+            [
+                "static/chunks/shared-s.js",
+                "static/chunks/pages/about-b.js"
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_build_manifest() {
+        let body = r#"self.__BUILD_MANIFEST = {"/": ["static/chunks/pages/index.js"]};"#;
+        let manifest = parse_build_manifest(body).unwrap();
+        assert_eq!(manifest.routes["/"], ["static/chunks/pages/index.js"]);
+    }
+
+    #[test]
+    fn ssg_manifest() {
+        let body = r#"self.__SSG_MANIFEST=new Set(["/blog","/about"]);self.__SSG_MANIFEST_CB&&self.__SSG_MANIFEST_CB()"#;
+        assert_eq!(parse_ssg_manifest(body), ["/blog", "/about"]);
+    }
+}
@@ -0,0 +1,308 @@
+//! Normalization of bundler specific source urls into relative paths.
+
+/// Directory virtual and generated modules are written to.
+pub const VIRTUAL_DIR: &str = "__virtual__";
+
+/// A source name reduced to a clean relative path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedSource {
+    /// Project name taken from the source url, e.g. `app` for `webpack://app/`.
+    pub project: Option<String>,
+    /// Path of the source relative to its project.
+    pub path: String,
+    /// Set for modules generated by the bundler that have no file on disk.
+    pub is_virtual: bool,
+}
+
+impl NormalizedSource {
+    fn new(project: Option<&str>, path: &str) -> Self {
+        Self {
+            project: project.filter(|p| !p.is_empty()).map(str::to_owned),
+            path: path.to_owned(),
+            is_virtual: false,
+        }
+    }
+
+    /// Returns the path the source is written to relative to the host
+    /// directory, `[__virtual__/][project/]path`.
+    pub fn relative_path(&self) -> String {
+        let mut parts = vec![];
+        if self.is_virtual {
+            parts.push(VIRTUAL_DIR);
+        }
+        if let Some(project) = &self.project {
+            parts.push(project);
+        }
+        parts.push(&self.path);
+        parts.join("/")
+    }
+}
+
+/// Maps one family of source urls onto a [`NormalizedSource`].
+///
+/// Returns `None` when the rule does not apply to the source. Implemented for
+/// any `Fn(&str) -> Option<NormalizedSource>`.
+pub trait SchemeRule: Send + Sync {
+    /// Normalizes `source`, or returns `None` if the rule does not apply.
+    fn normalize(&self, source: &str) -> Option<NormalizedSource>;
+}
+
+impl<F> SchemeRule for F
+where
+    F: Fn(&str) -> Option<NormalizedSource> + Send + Sync,
+{
+    fn normalize(&self, source: &str) -> Option<NormalizedSource> {
+        self(source)
+    }
+}
+
+/// Turns source names from a sourcemap into paths using a list of
+/// [`SchemeRule`]s, the first matching rule wins.
+pub struct SourceNormalizer {
+    rules: Vec<Box<dyn SchemeRule>>,
+}
+
+impl Default for SourceNormalizer {
+    /// Returns a normalizer with rules for the common bundlers.
+    fn default() -> Self {
+        Self::empty()
+            .with_rule(webpack_internal)
+            .with_rule(webpack)
+            .with_rule(angular)
+            .with_rule(turbopack)
+            .with_rule(rollup)
+            .with_rule(vite)
+            .with_rule(file)
+            .with_rule(http)
+            .with_rule(unknown_scheme)
+    }
+}
+
+impl SourceNormalizer {
+    /// Returns a normalizer without any rules.
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// Adds a rule, tried after the rules already added.
+    pub fn with_rule<R: SchemeRule + 'static>(mut self, rule: R) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Normalizes a source name.
+    ///
+    /// `source_root` is the map's `sourceRoot`, which is stripped again when
+    /// the decoder prefixed the source with it.
+    pub fn normalize(&self, source: &str, source_root: Option<&str>) -> NormalizedSource {
+        let mut source = source;
+        if let Some(root) = source_root.map(|r| r.trim_end_matches('/')) {
+            // roots that are only a scheme are left for the rules to handle
+            if !root.is_empty() && !root.ends_with(':') && !root.ends_with("://") {
+                if let Some(rest) = source.strip_prefix(root) {
+                    if rest.starts_with('/') {
+                        source = rest;
+                    }
+                }
+            }
+        }
+
+        let mut normalized = self
+            .rules
+            .iter()
+            .find_map(|rule| rule.normalize(source))
+            .unwrap_or_else(|| NormalizedSource::new(None, source));
+
+        let path = normalized.path.trim_start_matches('/');
+        let path = path.trim_start_matches("./");
+        normalized.is_virtual |= is_virtual_module(path);
+        normalized.path = path
+            .trim_start_matches('\0')
+            .trim_start_matches("__x00__")
+            .to_owned();
+        normalized
+    }
+}
+
+/// Returns true for modules the bundler generates, such as the webpack runtime
+/// or rollup's `\0` prefixed helpers.
+fn is_virtual_module(path: &str) -> bool {
+    const PREFIXES: [&str; 9] = [
+        "\0",
+        "__x00__",
+        "(webpack)",
+        "webpack/bootstrap",
+        "webpack/runtime/",
+        "webpack/universalModuleDefinition",
+        "[turbopack]",
+        "ignored|",
+        "external ",
+    ];
+
+    PREFIXES.iter().any(|p| path.starts_with(p)) || path.ends_with(" (ignored)")
+}
+
+/// Splits `<host>/<path>` after a scheme has been stripped.
+fn split_host(rest: &str) -> (&str, &str) {
+    rest.split_once('/').unwrap_or(("", rest))
+}
+
+/// `webpack-internal:///./src/app.js`
+fn webpack_internal(source: &str) -> Option<NormalizedSource> {
+    let rest = source.strip_prefix("webpack-internal://")?;
+    Some(NormalizedSource::new(None, rest))
+}
+
+/// `webpack:///src/app.js` and `webpack://<project>/src/app.js`
+fn webpack(source: &str) -> Option<NormalizedSource> {
+    let (project, path) = split_host(source.strip_prefix("webpack://")?);
+    Some(NormalizedSource::new(Some(project), path))
+}
+
+/// `ng://<project>/src/app.ts`
+fn angular(source: &str) -> Option<NormalizedSource> {
+    let (project, path) = split_host(source.strip_prefix("ng://")?);
+    Some(NormalizedSource::new(Some(project), path))
+}
+
+/// `turbopack:///[project]/src/app.js`
+fn turbopack(source: &str) -> Option<NormalizedSource> {
+    let rest = source.strip_prefix("turbopack://")?.trim_start_matches('/');
+    let rest = rest.strip_prefix("[project]/").unwrap_or(rest);
+    Some(NormalizedSource::new(None, rest))
+}
+
+/// `rollup://localhost/src/app.js`
+fn rollup(source: &str) -> Option<NormalizedSource> {
+    let rest = source.strip_prefix("rollup://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    Some(NormalizedSource::new(None, rest))
+}
+
+/// `vite:<module>` as well as `/@fs/<absolute path>` and `/@id/<module>`
+/// from the vite dev server.
+fn vite(source: &str) -> Option<NormalizedSource> {
+    if let Some(rest) = source.strip_prefix("/@fs/") {
+        return Some(NormalizedSource::new(None, rest));
+    }
+    if let Some(rest) = source.strip_prefix("/@id/") {
+        let mut normalized = NormalizedSource::new(None, rest);
+        normalized.is_virtual = true;
+        return Some(normalized);
+    }
+
+    let rest = source.strip_prefix("vite:")?;
+    Some(NormalizedSource::new(None, rest))
+}
+
+/// `file:///home/ci/project/src/app.js`
+fn file(source: &str) -> Option<NormalizedSource> {
+    let (_, path) = split_host(source.strip_prefix("file://")?);
+    Some(NormalizedSource::new(None, path))
+}
+
+/// `https://example.com/src/app.js`
+fn http(source: &str) -> Option<NormalizedSource> {
+    let rest = source
+        .strip_prefix("https://")
+        .or_else(|| source.strip_prefix("http://"))?;
+    let (_, path) = split_host(rest);
+    Some(NormalizedSource::new(None, path))
+}
+
+/// Any other `<scheme>://<host>/<path>`, the host is used as the project.
+fn unknown_scheme(source: &str) -> Option<NormalizedSource> {
+    let (scheme, rest) = source.split_once("://")?;
+    let is_scheme = scheme.len() > 1
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !is_scheme {
+        return None;
+    }
+
+    let (project, path) = split_host(rest);
+    Some(NormalizedSource::new(Some(project), path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(source: &str) -> String {
+        SourceNormalizer::default()
+            .normalize(source, None)
+            .relative_path()
+    }
+
+    #[test]
+    fn bundler_schemes() {
+        assert_eq!(relative("webpack:///./src/app.js"), "src/app.js");
+        assert_eq!(relative("webpack://app/./src/app.js"), "app/src/app.js");
+        assert_eq!(relative("webpack-internal:///./src/app.js"), "src/app.js");
+        assert_eq!(relative("ng://shop/src/app.ts"), "shop/src/app.ts");
+        assert_eq!(relative("turbopack:///[project]/src/app.js"), "src/app.js");
+        assert_eq!(relative("rollup://localhost/src/app.js"), "src/app.js");
+        assert_eq!(relative("vite:src/app.js"), "src/app.js");
+        assert_eq!(relative("/@fs/home/ci/src/app.js"), "home/ci/src/app.js");
+        assert_eq!(relative("file:///home/ci/src/app.js"), "home/ci/src/app.js");
+        assert_eq!(relative("https://cdn.com/lib/a.js"), "lib/a.js");
+        assert_eq!(relative("custom://proj/src/a.js"), "proj/src/a.js");
+        assert_eq!(relative("../src/app.js"), "../src/app.js");
+    }
+
+    #[test]
+    fn generated_modules_are_virtual() {
+        assert_eq!(
+            relative("webpack:///webpack/bootstrap"),
+            "__virtual__/webpack/bootstrap"
+        );
+        assert_eq!(
+            relative("webpack://app/external \"react\""),
+            "__virtual__/app/external \"react\""
+        );
+        assert_eq!(
+            relative("\0commonjsHelpers.js"),
+            "__virtual__/commonjsHelpers.js"
+        );
+        assert_eq!(
+            relative("/@id/__x00__vite/preload-helper"),
+            "__virtual__/vite/preload-helper"
+        );
+    }
+
+    #[test]
+    fn strips_source_root() {
+        let normalizer = SourceNormalizer::default();
+        let normalize = |source, root| normalizer.normalize(source, Some(root)).relative_path();
+        assert_eq!(normalize("/build/src/app.js", "/build/"), "src/app.js");
+        assert_eq!(normalize("/buildx/app.js", "/build"), "buildx/app.js");
+        // roots that are only a scheme are left for the rules
+        assert_eq!(
+            normalize("webpack://app/src/a.js", "webpack://"),
+            "app/src/a.js"
+        );
+    }
+
+    #[test]
+    fn rules_are_tried_in_order() {
+        let normalizer = SourceNormalizer::empty()
+            .with_rule(|source: &str| {
+                let path = source.strip_prefix("webpack:///")?;
+                Some(NormalizedSource::new(Some("custom"), path))
+            })
+            .with_rule(webpack);
+        let normalized = normalizer.normalize("webpack:///src/a.js", None);
+        assert_eq!(normalized.project.as_deref(), Some("custom"));
+        assert_eq!(
+            normalizer
+                .normalize("webpack://app/src/a.js", None)
+                .relative_path(),
+            "app/src/a.js"
+        );
+        assert_eq!(
+            normalizer.normalize("ng://shop/a.ts", None).relative_path(),
+            "ng://shop/a.ts"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Detecting Nuxt apps.
+//!
+//! Nuxt serves its chunks from `/_nuxt/`, or the `buildAssetsDir` of the
+//! runtime config in the `__NUXT__` payload, and the html links the chunks of
+//! other routes for prefetching. Nuxt 3 also publishes the routes it
+//! prerendered in `_nuxt/builds/meta/<buildId>.json`.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{fetch_file, find_references, Detection, FrameworkDetector, Page};
+use crate::js::{expression_end, parse_string};
+
+const DEFAULT_ASSETS_DIR: &str = "/_nuxt/";
+
+#[derive(Deserialize)]
+struct BuildMeta {
+    #[serde(default)]
+    prerendered: Vec<String>,
+}
+
+/// Detects Nuxt apps, listing the chunks the page refers to and the
+/// prerendered routes.
+///
+/// Only the major version can be told from the payload.
+pub struct NuxtDetector;
+
+#[async_trait]
+impl FrameworkDetector for NuxtDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        let body = page.body;
+        let assets_dir =
+            config_value(body, "buildAssetsDir").unwrap_or_else(|| DEFAULT_ASSETS_DIR.to_owned());
+        let version = if body.contains("__NUXT_DATA__") || body.contains("__NUXT__.config") {
+            Some("3")
+        } else if body.contains("__NUXT__") || body.contains("data-n-head") {
+            Some("2")
+        } else {
+            None
+        };
+        let serves_assets = page.scripts.iter().any(|s| s.path().contains(&assets_dir));
+        if version.is_none() && !serves_assets {
+            return None;
+        }
+
+        let build = config_value(body, "buildId");
+        let mut detection = Detection::new("nuxt")
+            .with_version(version.map(str::to_owned))
+            .with_build(build.clone());
+        detection.add_chunks(
+            find_references(body, &assets_dir)
+                .iter()
+                .filter_map(|r| page.url.join(r).ok()),
+        );
+
+        let meta_url = build.and_then(|build| {
+            let path = format!("{}builds/meta/{}.json", assets_dir, build);
+            page.url.join(&path).ok()
+        });
+        if let Some(meta_url) = meta_url {
+            let meta = fetch_file(page, client, &meta_url, errors)
+                .await
+                .and_then(|resp| serde_json::from_str::<BuildMeta>(&resp.body).ok());
+            for route in meta.map(|m| m.prerendered).unwrap_or_default() {
+                detection.routes.entry(route).or_default();
+            }
+        }
+
+        Some(detection)
+    }
+}
+
+/// Returns a string in the runtime config, written as `key:"value"` in the
+/// payload or `"key":"value"` as json.
+fn config_value(body: &str, key: &str) -> Option<String> {
+    body.match_indices(key).find_map(|(idx, _)| {
+        let rest = body[idx + key.len()..]
+            .strip_prefix('"')
+            .unwrap_or(&body[idx + key.len()..]);
+        let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+        parse_string(rest[..expression_end(rest)].trim()).filter(|v| !v.is_empty())
+    })
+}
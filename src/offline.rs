@@ -0,0 +1,215 @@
+//! Discovery of sourcemaps in local files, for bundles that are already on
+//! disk such as mobile app or electron dumps.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use crate::client::{Discovery, ScriptEntry};
+use crate::data_url::{decode_data_url, is_data_url};
+use crate::discovery::resolve_map_urls;
+use crate::error::ParsesmError;
+use crate::fetch::Headers;
+
+const SCRIPT_EXTENSIONS: [&str; 3] = ["js", "mjs", "cjs"];
+
+/// Finds the sourcemaps in a map file, a script or a directory of them.
+///
+/// Scripts use their `sourceMappingURL` directive, inline or pointing to a
+/// local file, and fall back to an adjacent `.map` file. Map files that no
+/// script refers to are reported on their own. Directories are walked
+/// recursively in name order without following symlinks, and maps are only
+/// read from inside the input.
+///
+/// The page url of the result is the directory of the input, every url is a
+/// `file://` url.
+pub fn discover_local(path: &Path) -> Result<Discovery, ParsesmError> {
+    let io_error = |source| ParsesmError::Io {
+        path: path.to_owned(),
+        source,
+    };
+
+    let input = path.canonicalize().map_err(io_error)?;
+    let (root, files) = if input.is_dir() {
+        let mut files = vec![];
+        walk(&input, &mut files).map_err(io_error)?;
+        (input, files)
+    } else {
+        let root = input.parent().unwrap_or(&input).to_owned();
+        (root, vec![input])
+    };
+
+    let mut claimed = HashSet::new();
+    let mut scripts = vec![];
+    for file in files
+        .iter()
+        .filter(|f| has_extension(f, &SCRIPT_EXTENSIONS))
+    {
+        scripts.push(read_script(&root, file, &mut claimed));
+    }
+    for file in files.iter().filter(|f| has_extension(f, &["map"])) {
+        if claimed.contains(file) {
+            continue;
+        }
+        let url = file_url(file);
+        let mut entry = ScriptEntry::new(&url);
+        match read_lossy(file) {
+            Ok(body) => {
+                entry.map_url = Some(url);
+                entry.map_body = Some(body);
+            }
+            Err(e) => entry.errors.push(e),
+        }
+        scripts.push(entry);
+    }
+
+    Ok(Discovery {
+        page_url: Url::from_directory_path(&root).expect("canonical paths are absolute"),
+        page_status: None,
+        scripts,
+        frameworks: vec![],
+        errors: vec![],
+    })
+}
+
+/// Finds the map of a local script, remembering map files that were used.
+fn read_script(root: &Path, file: &Path, claimed: &mut HashSet<PathBuf>) -> ScriptEntry {
+    let url = file_url(file);
+    let mut entry = ScriptEntry::new(&url);
+    let body = match read_lossy(file) {
+        Ok(body) => body,
+        Err(e) => {
+            entry.errors.push(e);
+            return entry;
+        }
+    };
+
+    for candidate in resolve_map_urls(&url, &Headers::new(), &body, false) {
+        if is_data_url(&candidate) {
+            if let Some(map_body) = decode_data_url(&candidate) {
+                entry.map_url = Some(url.clone());
+                entry.inline_map = true;
+                entry.map_body = Some(map_body);
+                return entry;
+            }
+            continue;
+        }
+
+        let map_path = match Url::parse(&candidate)
+            .ok()
+            .and_then(|u| u.to_file_path().ok())
+        {
+            Some(map_path) => map_path,
+            None => continue,
+        };
+        let map_path = match map_path.canonicalize() {
+            Ok(map_path) if map_path.is_file() && map_path.starts_with(root) => map_path,
+            _ => continue,
+        };
+        match read_lossy(&map_path) {
+            Ok(map_body) => {
+                entry.map_url = Some(file_url(&map_path));
+                entry.map_body = Some(map_body);
+                claimed.insert(map_path);
+                return entry;
+            }
+            Err(e) => entry.errors.push(e),
+        }
+    }
+
+    entry
+}
+
+/// Collects the regular files below `dir`, sorted by path.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_dir() {
+            walk(&path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| extensions.contains(&e))
+        .unwrap_or(false)
+}
+
+fn file_url(path: &Path) -> String {
+    Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|_| path.display().to_string())
+}
+
+/// Reads a file, replacing invalid utf-8 like responses from the network.
+fn read_lossy(path: &Path) -> Result<String, ParsesmError> {
+    let bytes = fs::read(path).map_err(|source| ParsesmError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_scripts_with_their_maps() {
+        let dir = std::env::temp_dir().join(format!("parsesm-{}-offline", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = dir.join("input");
+        fs::create_dir_all(input.join("js/maps")).unwrap();
+        let files = [
+            ("js/app.js", "//# sourceMappingURL=maps/app.js.map"),
+            ("js/maps/app.js.map", "{}"),
+            ("js/escape.js", "//# sourceMappingURL=../../outside.map"),
+            (
+                "js/inline.js",
+                "//# sourceMappingURL=data:application/json;base64,e30=",
+            ),
+            ("js/vendor.js", "console.log(1)"),
+            ("js/vendor.js.map", "{}"),
+            ("orphan.map", "{}"),
+        ];
+        for (name, contents) in files {
+            fs::write(input.join(name), contents).unwrap();
+        }
+        fs::write(dir.join("outside.map"), "{}").unwrap();
+
+        let discovery = discover_local(&input).unwrap();
+        let name = |url: &str| url.rsplit('/').next().unwrap().to_owned();
+        let scripts: Vec<_> = discovery
+            .scripts
+            .iter()
+            .map(|s| (name(&s.url), s.map_url.as_deref().map(name), s.inline_map))
+            .collect();
+        let entry = |script: &str, map: Option<&str>, inline| {
+            (script.to_owned(), map.map(str::to_owned), inline)
+        };
+        assert_eq!(
+            scripts,
+            [
+                entry("app.js", Some("app.js.map"), false),
+                entry("escape.js", None, false),
+                entry("inline.js", Some("inline.js"), true),
+                entry("vendor.js", Some("vendor.js.map"), false),
+                entry("orphan.map", Some("orphan.map"), false),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+//! Mapping of untrusted source names onto paths inside the output directory.
+
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use crate::manifest::sha256_hex;
+
+/// Characters that are not allowed in file names on at least one platform.
+const RESERVED_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Device names windows refuses to create files for, regardless of extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Returns the directory name used for a target, `host` or `host_port`.
+///
+/// Falls back to escaping the raw input when it is not a valid url.
+pub fn host_dir(target: &str) -> String {
+    match Url::parse(target) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => escape_component(&format!("{}_{}", host, port)),
+            (Some(host), None) => escape_component(host),
+            _ => escape_component(target),
+        },
+        Err(_) => escape_component(target),
+    }
+}
+
+/// Returns the output name of a target, unique per target.
+///
+/// Targets at the root of a host are named after it, see [`host_dir`]. Any
+/// other url gets the start of its sha256 appended, e.g. `a.com~3e23e816`,
+/// so targets sharing a host never write into the same output.
+pub fn target_dir(target: &str) -> String {
+    match Url::parse(target) {
+        Ok(url) if url.path() == "/" && url.query().is_none() => host_dir(target),
+        Ok(url) => format!(
+            "{}~{}",
+            host_dir(target),
+            &sha256_hex(url.as_str().as_bytes())[..8]
+        ),
+        Err(_) => host_dir(target),
+    }
+}
+
+/// A source name mapped onto a relative path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SanitizedPath {
+    /// The relative path.
+    pub path: PathBuf,
+    /// Whether `..` segments climbing above the root were dropped, so the
+    /// path is not where the source name points.
+    pub clamped: bool,
+}
+
+/// Normalizes a source name from a sourcemap into a relative path.
+///
+/// NUL bytes, drive letters and leading slashes are removed, `.` and `..`
+/// segments are resolved without ever climbing above the root, and reserved
+/// characters are escaped. `..` segments that would climb above it are
+/// dropped and reported as [`SanitizedPath::clamped`]. Returns `None` if
+/// nothing usable is left.
+pub fn sanitize_source_path(source: &str) -> Option<SanitizedPath> {
+    let cleaned: String = source
+        .chars()
+        .filter(|c| *c != '\0')
+        .map(|c| if c == '\\' { '/' } else { c })
+        .collect();
+
+    let mut rest = cleaned.as_str();
+    let bytes = rest.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        rest = &rest[2..];
+    }
+
+    let mut components: Vec<String> = vec![];
+    let mut clamped = false;
+    for segment in rest.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => clamped |= components.pop().is_none(),
+            _ => components.push(escape_component(segment)),
+        }
+    }
+
+    if components.is_empty() {
+        return None;
+    }
+
+    Some(SanitizedPath {
+        path: components.iter().collect(),
+        clamped,
+    })
+}
+
+/// Escapes a single path component so it is a valid file name everywhere.
+fn escape_component(component: &str) -> String {
+    let mut escaped = String::with_capacity(component.len());
+    for c in component.chars() {
+        if RESERVED_CHARS.contains(&c) || c.is_control() {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+
+    // windows silently drops trailing dots and spaces
+    if escaped.ends_with('.') || escaped.ends_with(' ') {
+        escaped.push('_');
+    }
+
+    let stem = escaped.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|n| n.eq_ignore_ascii_case(stem)) {
+        escaped.insert(stem.len(), '_');
+    }
+
+    escaped
+}
+
+/// Returns true if `path` resolves to a location inside `root`.
+///
+/// Both paths must exist. Symlinks are resolved so a link planted inside the
+/// output directory cannot redirect writes elsewhere.
+pub fn is_within(root: &Path, path: &Path) -> std::io::Result<bool> {
+    Ok(path.canonicalize()?.starts_with(root.canonicalize()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitized(source: &str) -> Option<String> {
+        sanitize_source_path(source).map(|p| p.path.to_string_lossy().replace('\\', "/"))
+    }
+
+    #[test]
+    fn targets_sharing_a_host_are_apart() {
+        assert_eq!(target_dir("https://a.com"), "a.com");
+        assert_eq!(target_dir("https://a.com:8443/"), "a.com_8443");
+        assert_eq!(target_dir("dist"), "dist");
+        let app = target_dir("https://a.com/app/");
+        let docs = target_dir("https://a.com/docs/");
+        assert!(app.starts_with("a.com~") && app.len() == "a.com~".len() + 8);
+        assert_ne!(app, docs);
+        assert_ne!(target_dir("https://a.com/?v=2"), "a.com");
+    }
+
+    #[test]
+    fn traversal_stays_below_root() {
+        assert_eq!(sanitized("../../etc/passwd").as_deref(), Some("etc/passwd"));
+        assert_eq!(sanitized("src/../../a.js").as_deref(), Some("a.js"));
+        assert_eq!(sanitized("/abs/./b.js").as_deref(), Some("abs/b.js"));
+        assert_eq!(sanitized(".."), None);
+        assert_eq!(sanitized("/"), None);
+    }
+
+    #[test]
+    fn climbing_above_root_is_reported() {
+        let clamped = |source| sanitize_source_path(source).unwrap().clamped;
+        assert!(clamped("../src/a.js"));
+        assert!(clamped("src/../../a.js"));
+        assert!(!clamped("src/../a.js"));
+        assert!(!clamped("/abs/./b.js"));
+    }
+
+    #[test]
+    fn drive_letters_and_backslashes() {
+        assert_eq!(
+            sanitized("C:\\Users\\dev\\app.js").as_deref(),
+            Some("Users/dev/app.js")
+        );
+        assert_eq!(sanitized("d:/x.js").as_deref(), Some("x.js"));
+    }
+
+    #[test]
+    fn nul_bytes_are_removed() {
+        assert_eq!(sanitized("src/a\0b.js").as_deref(), Some("src/ab.js"));
+    }
+
+    #[test]
+    fn reserved_names_and_chars_are_escaped() {
+        assert_eq!(sanitized("src/CON.ts").as_deref(), Some("src/CON_.ts"));
+        assert_eq!(sanitized("aux").as_deref(), Some("aux_"));
+        assert_eq!(sanitized("a<b>.js").as_deref(), Some("a%3Cb%3E.js"));
+        assert_eq!(sanitized("dir./a.js").as_deref(), Some("dir._/a.js"));
+    }
+}
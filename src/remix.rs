@@ -0,0 +1,90 @@
+//! Detecting Remix and React Router apps.
+//!
+//! The route manifest, assigned to `window.__remixManifest` or
+//! `window.__reactRouterManifest`, lists the module and imports of every
+//! route. It is inlined into the page or served as `manifest-<hash>.js`.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use url::Url;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{fetch_file, Detection, FrameworkDetector, Page};
+use crate::js::assigned_value;
+
+/// Global the manifest is assigned to and the framework it belongs to.
+const MANIFESTS: [(&str, &str); 2] = [
+    ("__remixManifest", "remix"),
+    ("__reactRouterManifest", "react-router"),
+];
+
+#[derive(Deserialize)]
+struct RouteManifest {
+    version: Option<String>,
+    entry: ManifestModule,
+    #[serde(default)]
+    routes: BTreeMap<String, ManifestModule>,
+}
+
+#[derive(Deserialize)]
+struct ManifestModule {
+    module: String,
+    #[serde(default)]
+    imports: Vec<String>,
+}
+
+impl ManifestModule {
+    fn chunks(&self, page_url: &Url) -> Vec<Url> {
+        std::iter::once(&self.module)
+            .chain(&self.imports)
+            .filter_map(|m| page_url.join(m).ok())
+            .collect()
+    }
+}
+
+/// Detects Remix and React Router apps, listing the chunks of every route by
+/// its id. The manifest version, a hash of the build, is reported as the
+/// build.
+///
+/// Neither framework publishes its own version, so none is reported.
+pub struct RemixDetector;
+
+#[async_trait]
+impl FrameworkDetector for RemixDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        let (framework, manifest) = match parse_manifest(page.body) {
+            Some(found) => found,
+            None => {
+                let manifest_url = page.scripts.iter().find(|s| {
+                    let name = s.path_segments().and_then(|mut p| p.next_back());
+                    name.map(|n| n.starts_with("manifest-") && n.ends_with(".js"))
+                        .unwrap_or(false)
+                })?;
+                let resp = fetch_file(page, client, manifest_url, errors).await?;
+                parse_manifest(&resp.body)?
+            }
+        };
+
+        let mut detection = Detection::new(framework).with_build(manifest.version);
+        detection.add_chunks(manifest.entry.chunks(page.url));
+        for (id, route) in &manifest.routes {
+            detection.add_route(id.clone(), route.chunks(page.url));
+        }
+        Some(detection)
+    }
+}
+
+fn parse_manifest(body: &str) -> Option<(&'static str, RouteManifest)> {
+    MANIFESTS.iter().find_map(|(name, framework)| {
+        let value = assigned_value(body, name)?;
+        Some((*framework, serde_json::from_str(value).ok()?))
+    })
+}
@@ -0,0 +1,176 @@
+//! Replaying captured traffic instead of touching the network.
+//!
+//! A [`Capture`] indexes the responses recorded in a [HAR](crate::har) or
+//! [WARC](crate::warc) file by url, a [`ReplayFetcher`] serves them. Captures
+//! of a browser session also contain every chunk the page loaded lazily,
+//! which parsing the initial html misses, see [`Capture::script_urls`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::header::{CONTENT_TYPE, LOCATION};
+use url::Url;
+
+use crate::error::ParsesmError;
+use crate::fetch::{Fetcher, Headers, Response};
+
+/// Redirects followed within a capture before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+/// A captured response.
+#[derive(Debug, Clone)]
+struct Captured {
+    status: u16,
+    headers: Headers,
+    /// `None` when the capture did not keep the body.
+    body: Option<String>,
+}
+
+/// Responses of captured traffic, indexed by url.
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    responses: HashMap<Url, Captured>,
+    /// Urls in the order they were first requested.
+    order: Vec<Url>,
+}
+
+impl Capture {
+    /// Adds a response for `url`.
+    ///
+    /// When a url was captured several times the first successful response
+    /// is kept, or the last response if none succeeded.
+    pub fn insert(&mut self, mut url: Url, status: u16, headers: Headers, body: Option<String>) {
+        url.set_fragment(None);
+        let captured = Captured {
+            status,
+            headers,
+            body,
+        };
+
+        match self.responses.get(&url) {
+            Some(existing) if is_success(existing.status) => {}
+            Some(_) => {
+                self.responses.insert(url, captured);
+            }
+            None => {
+                self.order.push(url.clone());
+                self.responses.insert(url, captured);
+            }
+        }
+    }
+
+    /// Returns the url of the first html document in the capture, the page
+    /// the session most likely started on.
+    pub fn page_url(&self) -> Option<&Url> {
+        self.order.iter().find(|url| {
+            let captured = &self.responses[*url];
+            is_success(captured.status) && content_type(captured).contains("html")
+        })
+    }
+
+    /// Returns every successfully captured script, in request order.
+    pub fn script_urls(&self) -> Vec<Url> {
+        self.order
+            .iter()
+            .filter(|url| {
+                let captured = &self.responses[*url];
+                is_success(captured.status)
+                    && (content_type(captured).contains("javascript")
+                        || matches!(extension(url), "js" | "mjs" | "cjs"))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Number of distinct urls in the capture.
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Whether the capture has no responses.
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+}
+
+/// Serves the responses of a [`Capture`].
+///
+/// Urls missing from the capture, or captured without their body, are
+/// answered with a 404 unless a fallback fetcher is set to fetch them live.
+pub struct ReplayFetcher {
+    capture: Capture,
+    fallback: Option<Arc<dyn Fetcher>>,
+}
+
+impl ReplayFetcher {
+    /// Creates a fetcher replaying `capture`.
+    pub fn new(capture: Capture) -> Self {
+        Self {
+            capture,
+            fallback: None,
+        }
+    }
+
+    /// Sets the fetcher used for urls the capture does not have.
+    pub fn with_fallback(mut self, fallback: Option<Arc<dyn Fetcher>>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReplayFetcher {
+    async fn get(&self, url: &Url) -> Result<Response, ParsesmError> {
+        let mut current = url.clone();
+        current.set_fragment(None);
+
+        for _ in 0..=MAX_REDIRECTS {
+            let captured = match self.capture.responses.get(&current) {
+                Some(captured) => captured,
+                None => break,
+            };
+
+            let location = captured
+                .headers
+                .get(LOCATION.as_str())
+                .and_then(|l| current.join(l).ok());
+            if let (300..=399, Some(location)) = (captured.status, location) {
+                current = location;
+                continue;
+            }
+
+            let body = match &captured.body {
+                Some(body) => body.clone(),
+                None => break,
+            };
+            let mut response = Response::new(current, captured.status, body);
+            response.headers = captured.headers.clone();
+            return Ok(response);
+        }
+
+        match &self.fallback {
+            Some(fallback) => fallback.get(&current).await,
+            None => Ok(Response::new(current, 404, "")),
+        }
+    }
+}
+
+fn content_type(captured: &Captured) -> &str {
+    captured
+        .headers
+        .get(CONTENT_TYPE.as_str())
+        .unwrap_or_default()
+}
+
+fn is_success(status: u16) -> bool {
+    (200..300).contains(&status)
+}
+
+fn extension(url: &Url) -> &str {
+    let path = url.path();
+    let name = &path[path.rfind('/').map(|i| i + 1).unwrap_or(0)..];
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default()
+}
@@ -0,0 +1,276 @@
+//! Summaries of extraction runs and the exit codes they map to.
+
+use serde::Serialize;
+
+use crate::error::ParsesmError;
+
+/// Process exit codes. Invalid arguments exit with 2, which clap reports on
+/// its own.
+pub mod exit_code {
+    /// Every map found was processed without problems.
+    pub const OK: u8 = 0;
+    /// An unexpected error such as an unreadable input file.
+    pub const FAILURE: u8 = 1;
+    /// The page was fetched but none of its scripts had a sourcemap.
+    pub const NO_MAPS: u8 = 3;
+    /// Some maps or sources could not be processed.
+    pub const PARTIAL: u8 = 4;
+    /// The target page could not be fetched.
+    pub const NETWORK: u8 = 5;
+}
+
+/// Help text describing the exit codes.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  all sourcemaps were extracted
+  1  unexpected error
+  2  invalid arguments
+  3  no sourcemaps found
+  4  partial extraction, some maps or sources failed
+  5  the target could not be fetched";
+
+/// How an extraction run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Every map was decoded and every source recovered.
+    Complete,
+    /// Maps were found but some maps or sources were lost.
+    Partial,
+    /// The page was fetched but none of its scripts had a map.
+    NoMaps,
+    /// The page could not be fetched.
+    NetworkFailure,
+    /// The target could not be processed at all.
+    Failed,
+}
+
+impl Outcome {
+    /// Short machine readable name of the outcome.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Complete => "complete",
+            Outcome::Partial => "partial",
+            Outcome::NoMaps => "no_maps",
+            Outcome::NetworkFailure => "network_failure",
+            Outcome::Failed => "failed",
+        }
+    }
+
+    /// Process exit code for the outcome.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Outcome::Complete => exit_code::OK,
+            Outcome::Partial => exit_code::PARTIAL,
+            Outcome::NoMaps => exit_code::NO_MAPS,
+            Outcome::NetworkFailure => exit_code::NETWORK,
+            Outcome::Failed => exit_code::FAILURE,
+        }
+    }
+}
+
+/// Summary of extracting the sourcemaps of a single target.
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct ExtractReport {
+    /// The target as given on the command line.
+    pub target: String,
+    /// Why the target could not be processed at all, e.g. it was not a url.
+    pub error: Option<String>,
+    /// Why the target page could not be fetched.
+    pub page_error: Option<String>,
+    /// Scripts in scope on the page.
+    pub scripts: usize,
+    /// Sourcemaps fetched for those scripts.
+    pub maps: usize,
+    /// Sourcemaps that failed to decode.
+    pub invalid_maps: usize,
+    /// Sources written to disk.
+    pub written: usize,
+    /// Sources identical to a file already on disk.
+    pub duplicates: usize,
+    /// Sources that differed from a file already on disk.
+    pub conflicts: usize,
+    /// Sources that could not be written.
+    pub write_errors: usize,
+    /// Sources without content that could not be downloaded either.
+    pub unrecovered: Vec<String>,
+    /// Errors encountered during the run.
+    pub errors: ErrorCounts,
+}
+
+/// Number of errors of each kind seen during a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ErrorCounts {
+    /// Malformed urls.
+    pub invalid_url: usize,
+    /// Requests that failed without a response.
+    pub network: usize,
+    /// Responses with an unsuccessful status.
+    pub http_status: usize,
+    /// Sourcemaps that could not be decoded.
+    pub decode: usize,
+    /// Sourcemaps that were not valid json.
+    pub json: usize,
+    /// Scripts that could not be parsed.
+    pub parse: usize,
+    /// Failed writes.
+    pub io: usize,
+    /// Sources that would have been written outside the output directory.
+    pub path_safety: usize,
+}
+
+impl ErrorCounts {
+    /// Counts `error` under its kind.
+    pub fn record(&mut self, error: &ParsesmError) {
+        let count = match error {
+            ParsesmError::InvalidUrl { .. } => &mut self.invalid_url,
+            ParsesmError::Network { .. } => &mut self.network,
+            ParsesmError::HttpStatus { .. } => &mut self.http_status,
+            ParsesmError::Decode { .. } => &mut self.decode,
+            ParsesmError::Json { .. } => &mut self.json,
+            ParsesmError::Parse { .. } => &mut self.parse,
+            ParsesmError::Io { .. } => &mut self.io,
+            ParsesmError::PathSafety { .. } => &mut self.path_safety,
+        };
+        *count += 1;
+    }
+
+    /// Number of errors of any kind.
+    pub fn total(&self) -> usize {
+        self.invalid_url
+            + self.network
+            + self.http_status
+            + self.decode
+            + self.json
+            + self.parse
+            + self.io
+            + self.path_safety
+    }
+}
+
+impl ExtractReport {
+    /// Creates an empty report for `target`.
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Classifies the run, see [`Outcome`].
+    pub fn outcome(&self) -> Outcome {
+        if self.error.is_some() {
+            Outcome::Failed
+        } else if self.page_error.is_some() {
+            Outcome::NetworkFailure
+        } else if self.maps == 0 {
+            Outcome::NoMaps
+        } else if self.invalid_maps > 0
+            || self.write_errors > 0
+            || self.errors.total() > 0
+            || !self.unrecovered.is_empty()
+        {
+            Outcome::Partial
+        } else {
+            Outcome::Complete
+        }
+    }
+}
+
+/// Returns the exit code for a run over several targets.
+///
+/// When every target ended the same way that outcome decides, otherwise the
+/// run counts as partial.
+pub fn batch_exit_code(reports: &[ExtractReport]) -> u8 {
+    let mut outcomes = reports.iter().map(ExtractReport::outcome);
+    match outcomes.next() {
+        Some(first) if outcomes.all(|o| o == first) => first.exit_code(),
+        Some(_) => exit_code::PARTIAL,
+        None => exit_code::NO_MAPS,
+    }
+}
+
+/// Prints a table summarizing the reports of a batch run.
+pub fn print_summary(reports: &[ExtractReport]) {
+    let width = reports
+        .iter()
+        .map(|r| r.target.len())
+        .max()
+        .unwrap_or(0)
+        .max("TARGET".len());
+
+    println!(
+        "{:<width$}  {:<15}  {:>6}  {:>7}  {:>11}  {:>6}",
+        "TARGET",
+        "OUTCOME",
+        "MAPS",
+        "SOURCES",
+        "UNRECOVERED",
+        "ERRORS",
+        width = width
+    );
+    for report in reports {
+        println!(
+            "{:<width$}  {:<15}  {:>6}  {:>7}  {:>11}  {:>6}",
+            report.target,
+            report.outcome().as_str(),
+            report.maps,
+            report.written,
+            report.unrecovered.len(),
+            report.errors.total(),
+            width = width
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(maps: usize, invalid_maps: usize) -> ExtractReport {
+        ExtractReport {
+            maps,
+            invalid_maps,
+            ..ExtractReport::new("https://a.com/")
+        }
+    }
+
+    #[test]
+    fn failures_take_precedence() {
+        let mut report = report(1, 1);
+        assert_eq!(report.outcome(), Outcome::Partial);
+        report.page_error = Some("timed out".to_owned());
+        assert_eq!(report.outcome(), Outcome::NetworkFailure);
+        report.error = Some("not a url".to_owned());
+        assert_eq!(report.outcome(), Outcome::Failed);
+    }
+
+    #[test]
+    fn mixed_batches_are_partial() {
+        assert_eq!(batch_exit_code(&[report(1, 0)]), exit_code::OK);
+        assert_eq!(
+            batch_exit_code(&[report(0, 0), report(0, 0)]),
+            exit_code::NO_MAPS
+        );
+        assert_eq!(
+            batch_exit_code(&[report(1, 0), report(0, 0)]),
+            exit_code::PARTIAL
+        );
+        assert_eq!(batch_exit_code(&[]), exit_code::NO_MAPS);
+    }
+
+    #[test]
+    fn errors_make_the_run_partial() {
+        let mut report = report(1, 0);
+        assert_eq!(report.outcome(), Outcome::Complete);
+        report.errors.record(&ParsesmError::HttpStatus {
+            url: "https://a.com/src/a.ts".to_owned(),
+            status: 404,
+        });
+        assert_eq!(report.errors.http_status, 1);
+        assert_eq!(report.errors.total(), 1);
+        assert_eq!(report.outcome(), Outcome::Partial);
+    }
+}
@@ -0,0 +1,174 @@
+//! Policies deciding which script urls are followed from a page.
+
+use std::str::FromStr;
+
+use url::Url;
+
+/// Controls which scripts referenced by a page are followed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// Only scripts with the same scheme, host and port as the page.
+    SameOrigin,
+    /// Scripts sharing the page's registrable domain, e.g. `cdn.example.com`
+    /// for a page on `www.example.com`.
+    #[default]
+    SameSite,
+    /// Same origin scripts plus any host in the list. Entries also match their
+    /// subdomains.
+    Allowlist(Vec<String>),
+    /// Every http(s) script regardless of host.
+    Any,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    /// Parses `same-origin`, `same-site`, `any` or `allow:<host>,<host>...`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same-origin" => Ok(Scope::SameOrigin),
+            "same-site" => Ok(Scope::SameSite),
+            "any" => Ok(Scope::Any),
+            _ => match s.strip_prefix("allow:") {
+                Some(hosts) => Ok(Scope::Allowlist(
+                    hosts
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|h| !h.is_empty())
+                        .map(str::to_owned)
+                        .collect(),
+                )),
+                None => Err(format!(
+                    "invalid scope `{}`, expected same-origin, same-site, any or allow:<hosts>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Scope {
+    /// Returns true if `script` should be followed from a page at `page`.
+    pub fn allows(&self, page: &Url, script: &Url) -> bool {
+        if !matches!(script.scheme(), "http" | "https") {
+            return false;
+        }
+
+        let same_origin = page.origin() == script.origin();
+        match self {
+            Scope::SameOrigin => same_origin,
+            Scope::SameSite => {
+                same_origin
+                    || match (page.host_str(), script.host_str()) {
+                        (Some(a), Some(b)) => registrable_domain(a) == registrable_domain(b),
+                        _ => false,
+                    }
+            }
+            Scope::Allowlist(hosts) => {
+                same_origin
+                    || script
+                        .host_str()
+                        .map(|host| hosts.iter().any(|allowed| host_matches(host, allowed)))
+                        .unwrap_or(false)
+            }
+            Scope::Any => true,
+        }
+    }
+}
+
+/// Returns the registrable domain of a host, falling back to the host itself
+/// for ip addresses and hosts unknown to the public suffix list.
+fn registrable_domain(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    psl::domain_str(&host).map(str::to_owned).unwrap_or(host)
+}
+
+fn host_matches(host: &str, allowed: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let allowed = allowed.trim_end_matches('.').to_ascii_lowercase();
+    host == allowed || host.ends_with(&format!(".{}", allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allows(scope: &str, page: &str, script: &str) -> bool {
+        let page = Url::parse(page).unwrap();
+        let script = Url::parse(script).unwrap();
+        scope.parse::<Scope>().unwrap().allows(&page, &script)
+    }
+
+    #[test]
+    fn same_origin_needs_scheme_host_and_port() {
+        assert!(allows(
+            "same-origin",
+            "https://a.com/",
+            "https://a.com/app.js"
+        ));
+        assert!(!allows(
+            "same-origin",
+            "https://a.com/",
+            "http://a.com/app.js"
+        ));
+        assert!(!allows(
+            "same-origin",
+            "https://a.com/",
+            "https://a.com:8443/app.js"
+        ));
+        assert!(!allows(
+            "same-origin",
+            "https://a.com/",
+            "https://cdn.a.com/app.js"
+        ));
+    }
+
+    #[test]
+    fn same_site_uses_registrable_domain() {
+        assert!(allows(
+            "same-site",
+            "https://www.a.co.uk/",
+            "https://cdn.a.co.uk/app.js"
+        ));
+        assert!(!allows(
+            "same-site",
+            "https://a.co.uk/",
+            "https://b.co.uk/app.js"
+        ));
+        assert!(!allows(
+            "same-site",
+            "https://127.0.0.1/",
+            "https://127.0.0.2/app.js"
+        ));
+    }
+
+    #[test]
+    fn allowlist_matches_subdomains() {
+        let scope = "allow:cdn.com, static.b.com";
+        assert!(allows(scope, "https://a.com/", "https://a.com/app.js"));
+        assert!(allows(scope, "https://a.com/", "https://eu.cdn.com/app.js"));
+        assert!(allows(
+            scope,
+            "https://a.com/",
+            "https://STATIC.b.com./app.js"
+        ));
+        assert!(!allows(scope, "https://a.com/", "https://b.com/app.js"));
+        assert!(!allows(
+            scope,
+            "https://a.com/",
+            "https://notcdn.com/app.js"
+        ));
+    }
+
+    #[test]
+    fn any_still_needs_http() {
+        assert!(allows("any", "https://a.com/", "http://b.org/app.js"));
+        assert!(!allows("any", "https://a.com/", "data:text/javascript,1"));
+    }
+
+    #[test]
+    fn rejects_unknown_scopes() {
+        assert!("everything".parse::<Scope>().is_err());
+        assert_eq!("allow:".parse::<Scope>(), Ok(Scope::Allowlist(vec![])));
+    }
+}
@@ -0,0 +1,570 @@
+//! Where recovered sources and manifests are stored.
+//!
+//! A [`SourceWriter`](crate::SourceWriter) decides which path a source gets,
+//! a [`Sink`] stores it: in a directory, a `.tar.gz` or `.zip` archive, or in
+//! memory. Paths handed to a sink are already sanitized and relative to the
+//! target.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::error::ParsesmError;
+use crate::manifest::sha256_hex;
+use crate::paths::{is_within, target_dir};
+
+/// Storage for the output of a single target.
+pub trait Sink: Send {
+    /// Describes where the output ends up, e.g. the directory or archive path.
+    fn location(&self) -> String;
+
+    /// Returns the sha256 of the file at `path`, if there is one.
+    fn existing(&self, path: &Path) -> Result<Option<String>, ParsesmError>;
+
+    /// Stores `contents` at `path`, replacing any previous file.
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), ParsesmError>;
+
+    /// Completes the output, nothing may be written afterwards.
+    fn finish(&mut self) -> Result<(), ParsesmError> {
+        Ok(())
+    }
+}
+
+/// Creates the [`Sink`] for each target of a run.
+///
+/// Implemented for any `Fn(&str) -> Result<Box<dyn Sink>, ParsesmError>`.
+pub trait SinkFactory: Send + Sync {
+    /// Creates the sink for `target`.
+    fn create(&self, target: &str) -> Result<Box<dyn Sink>, ParsesmError>;
+}
+
+impl<F> SinkFactory for F
+where
+    F: Fn(&str) -> Result<Box<dyn Sink>, ParsesmError> + Send + Sync,
+{
+    fn create(&self, target: &str) -> Result<Box<dyn Sink>, ParsesmError> {
+        self(target)
+    }
+}
+
+/// Archive formats a target can be written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A gzip compressed tarball, written as sources come in.
+    TarGz,
+    /// A zip archive, written once the target is done.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// File extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGz),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(format!(
+                "invalid archive format `{}`, expected tar.gz or zip",
+                s
+            )),
+        }
+    }
+}
+
+/// Writes every target under one output directory, either into
+/// `<dir>/<name>/` or into a `<dir>/<name>.<ext>` archive. Targets are named
+/// after their host, those below the root get the start of their sha256
+/// appended, e.g. `a.com~3e23e816`.
+#[derive(Debug, Clone)]
+pub struct OutputDir {
+    dir: PathBuf,
+    archive: Option<ArchiveFormat>,
+}
+
+impl OutputDir {
+    /// Creates a factory writing plain directories under `dir`.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            archive: None,
+        }
+    }
+
+    /// Sets whether each target is packed into an archive.
+    pub fn with_archive(mut self, archive: Option<ArchiveFormat>) -> Self {
+        self.archive = archive;
+        self
+    }
+}
+
+impl SinkFactory for OutputDir {
+    fn create(&self, target: &str) -> Result<Box<dyn Sink>, ParsesmError> {
+        let name = target_dir(target);
+        Ok(match self.archive {
+            None => Box::new(DirSink::new(self.dir.join(name))),
+            Some(format) => {
+                let path = self.dir.join(format!("{}.{}", name, format.extension()));
+                match format {
+                    ArchiveFormat::TarGz => Box::new(TarGzSink::create(path)?),
+                    ArchiveFormat::Zip => Box::new(ZipSink::create(path)?),
+                }
+            }
+        })
+    }
+}
+
+/// Writes files below a root directory.
+///
+/// Files are never written through symlinks or outside the root, even when
+/// the directory was tampered with between runs.
+pub struct DirSink {
+    root: PathBuf,
+}
+
+impl DirSink {
+    /// Creates a sink writing below `root`, which is created on demand.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Directory the files are written to.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the full path for `path`, failing if it escapes the root.
+    ///
+    /// With `create`, directories below the root are created one at a time,
+    /// and a symlink or parent component is refused before anything is
+    /// created through it. Without it nothing is created and `None` is
+    /// returned when a directory is missing, as the file cannot exist.
+    fn target(&self, path: &Path, create: bool) -> Result<Option<PathBuf>, ParsesmError> {
+        let escapes = || ParsesmError::PathSafety {
+            name: path.display().to_string(),
+            reason: "it escapes the output directory".to_owned(),
+        };
+        if create {
+            fs::create_dir_all(&self.root).map_err(|e| io_error(&self.root, e))?;
+        } else if !self.root.exists() {
+            return Ok(None);
+        }
+
+        // paths given to sinks always have at least a file name
+        let parent = path.parent().expect("failed to get parent dir");
+        let mut dir = self.root.clone();
+        for component in parent.components() {
+            match component {
+                Component::Normal(name) => dir.push(name),
+                Component::CurDir => continue,
+                _ => return Err(escapes()),
+            }
+            match fs::symlink_metadata(&dir) {
+                Ok(meta) if meta.file_type().is_symlink() => return Err(escapes()),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && !create => return Ok(None),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    match fs::create_dir(&dir) {
+                        Ok(()) => {}
+                        // created concurrently by another write
+                        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                        Err(e) => return Err(io_error(&dir, e)),
+                    }
+                }
+                Err(e) => return Err(io_error(&dir, e)),
+            }
+        }
+
+        let target = self.root.join(path);
+        let within = is_within(&self.root, &dir).map_err(|e| io_error(&dir, e))?;
+        let is_symlink = fs::symlink_metadata(&target)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if !within || is_symlink {
+            return Err(escapes());
+        }
+
+        Ok(Some(target))
+    }
+}
+
+impl Sink for DirSink {
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn existing(&self, path: &Path) -> Result<Option<String>, ParsesmError> {
+        let target = match self.target(path, false)? {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        match fs::read(&target) {
+            Ok(existing) => Ok(Some(sha256_hex(&existing))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(&target, e)),
+        }
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), ParsesmError> {
+        let target = self.target(path, true)?.expect("directories are created");
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&target)
+            .map_err(|e| io_error(&target, e))?;
+
+        file.write_all(contents).map_err(|e| io_error(&target, e))
+    }
+}
+
+/// Streams files into a `.tar.gz` archive.
+///
+/// A file written twice is appended twice, extracting the archive keeps the
+/// last copy.
+pub struct TarGzSink {
+    path: PathBuf,
+    builder: Option<tar::Builder<GzEncoder<File>>>,
+    written: HashMap<PathBuf, String>,
+    mtime: u64,
+}
+
+impl TarGzSink {
+    /// Creates the archive at `path`, replacing any previous one.
+    pub fn create<P: Into<PathBuf>>(path: P) -> Result<Self, ParsesmError> {
+        let path = path.into();
+        let file = create_file(&path)?;
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Ok(Self {
+            builder: Some(tar::Builder::new(GzEncoder::new(
+                file,
+                Compression::default(),
+            ))),
+            path,
+            written: HashMap::new(),
+            mtime,
+        })
+    }
+}
+
+impl Sink for TarGzSink {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn existing(&self, path: &Path) -> Result<Option<String>, ParsesmError> {
+        Ok(self.written.get(path).cloned())
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), ParsesmError> {
+        let builder = self.builder.as_mut().expect("sink already finished");
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        header.set_entry_type(tar::EntryType::Regular);
+
+        builder
+            .append_data(&mut header, path, contents)
+            .map_err(|e| io_error(&self.path, e))?;
+        self.written.insert(path.to_owned(), sha256_hex(contents));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ParsesmError> {
+        if let Some(builder) = self.builder.take() {
+            builder
+                .into_inner()
+                .and_then(|gz| gz.finish())
+                .map_err(|e| io_error(&self.path, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects files and writes them into a `.zip` archive when finished.
+///
+/// Zip archives cannot hold a file twice, so files are kept in memory until
+/// the target is done.
+pub struct ZipSink {
+    path: PathBuf,
+    file: Option<File>,
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl ZipSink {
+    /// Creates the archive at `path`, replacing any previous one.
+    pub fn create<P: Into<PathBuf>>(path: P) -> Result<Self, ParsesmError> {
+        let path = path.into();
+        let file = create_file(&path)?;
+        Ok(Self {
+            path,
+            file: Some(file),
+            files: BTreeMap::new(),
+        })
+    }
+}
+
+impl Sink for ZipSink {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn existing(&self, path: &Path) -> Result<Option<String>, ParsesmError> {
+        Ok(self.files.get(path).map(|contents| sha256_hex(contents)))
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), ParsesmError> {
+        self.files.insert(path.to_owned(), contents.to_vec());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ParsesmError> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let zip_error = |e: zip::result::ZipError| io_error(&self.path, e.into());
+        let mut zip = ZipWriter::new(file);
+        for (path, contents) in &self.files {
+            zip.start_file(archive_name(path), FileOptions::default())
+                .map_err(zip_error)?;
+            zip.write_all(contents)
+                .map_err(|e| io_error(&self.path, e))?;
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(())
+    }
+}
+
+/// Keeps files in memory, for library users.
+///
+/// Clones share their files. Used as a [`SinkFactory`] every target is kept
+/// under its own `<host>/` prefix.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    files: Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>,
+    prefix: PathBuf,
+}
+
+impl MemorySink {
+    /// Creates an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of every file written so far.
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.files.lock().unwrap().clone()
+    }
+}
+
+impl Sink for MemorySink {
+    fn location(&self) -> String {
+        format!("memory:{}", self.prefix.display())
+    }
+
+    fn existing(&self, path: &Path) -> Result<Option<String>, ParsesmError> {
+        let files = self.files.lock().unwrap();
+        Ok(files
+            .get(&self.prefix.join(path))
+            .map(|contents| sha256_hex(contents)))
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), ParsesmError> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(self.prefix.join(path), contents.to_vec());
+        Ok(())
+    }
+}
+
+impl SinkFactory for MemorySink {
+    fn create(&self, target: &str) -> Result<Box<dyn Sink>, ParsesmError> {
+        Ok(Box::new(MemorySink {
+            files: self.files.clone(),
+            prefix: PathBuf::from(target_dir(target)),
+        }))
+    }
+}
+
+/// Returns `path` with forward slashes, as archives and manifests expect.
+pub fn archive_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn create_file(path: &Path) -> Result<File, ParsesmError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+    }
+    File::create(path).map_err(|e| io_error(path, e))
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> ParsesmError {
+    ParsesmError::Io {
+        path: path.to_owned(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("parsesm-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_below_root() {
+        let dir = temp_dir("sink-write");
+        let mut sink = DirSink::new(dir.join("out"));
+        sink.write(Path::new("src/a/b.js"), b"b").unwrap();
+        assert_eq!(fs::read(dir.join("out/src/a/b.js")).unwrap(), b"b");
+        assert_eq!(
+            sink.existing(Path::new("src/a/b.js")).unwrap(),
+            Some(sha256_hex(b"b"))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn looking_up_creates_nothing() {
+        let dir = temp_dir("sink-existing");
+        let sink = DirSink::new(dir.join("out"));
+        assert_eq!(sink.existing(Path::new("src/a/b.js")).unwrap(), None);
+        assert!(!dir.join("out").exists());
+
+        fs::create_dir_all(dir.join("out")).unwrap();
+        assert_eq!(sink.existing(Path::new("src/a/b.js")).unwrap(), None);
+        assert!(!dir.join("out/src").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tar_gz_appends_every_write() {
+        let dir = temp_dir("sink-tar");
+        let path = dir.join("a.com.tar.gz");
+        let mut sink = TarGzSink::create(&path).unwrap();
+        sink.write(Path::new("src/a.js"), b"old").unwrap();
+        sink.write(Path::new("src/a.js"), b"new").unwrap();
+        assert_eq!(
+            sink.existing(Path::new("src/a.js")).unwrap(),
+            Some(sha256_hex(b"new"))
+        );
+        sink.finish().unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| archive_name(&entry.unwrap().path().unwrap()))
+            .collect();
+        assert_eq!(names, ["src/a.js", "src/a.js"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn zip_keeps_the_last_write() {
+        use std::io::Read;
+
+        let dir = temp_dir("sink-zip");
+        let path = dir.join("a.com.zip");
+        let mut sink = ZipSink::create(&path).unwrap();
+        sink.write(Path::new("src/a.js"), b"old").unwrap();
+        sink.write(Path::new("src/a.js"), b"new").unwrap();
+        sink.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+        let mut contents = String::new();
+        archive
+            .by_name("src/a.js")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_targets_are_kept_apart() {
+        let memory = MemorySink::new();
+        for target in ["https://a.com/", "https://b.com/"] {
+            let mut sink = memory.create(target).unwrap();
+            sink.write(Path::new("src/a.js"), target.as_bytes())
+                .unwrap();
+        }
+        let files = memory.files();
+        assert_eq!(files[Path::new("a.com/src/a.js")], b"https://a.com/");
+        assert_eq!(files[Path::new("b.com/src/a.js")], b"https://b.com/");
+    }
+
+    #[test]
+    fn targets_on_one_host_are_kept_apart() {
+        let dir = temp_dir("sink-same-host");
+        let output = OutputDir::new(&dir).with_archive(Some(ArchiveFormat::Zip));
+        for target in ["https://a.com/", "https://a.com/app/"] {
+            let mut sink = output.create(target).unwrap();
+            sink.write(Path::new("manifest.json"), target.as_bytes())
+                .unwrap();
+            sink.finish().unwrap();
+        }
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "a.com.zip");
+        assert!(names[1].starts_with("a.com~"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinked_directories() {
+        let dir = temp_dir("sink-symlink");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("out/src")).unwrap();
+
+        let mut sink = DirSink::new(dir.join("out"));
+        let result = sink.write(Path::new("src/nested/a.js"), b"a");
+        assert!(matches!(result, Err(ParsesmError::PathSafety { .. })));
+        assert!(!outside.join("nested").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
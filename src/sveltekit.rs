@@ -0,0 +1,65 @@
+//! Detecting SvelteKit apps.
+//!
+//! SvelteKit serves its chunks from `_app/immutable/` and starts the app by
+//! importing its `entry/start` and `entry/app` modules, which import the
+//! nodes of every route in turn. The deployed version is published in
+//! `_app/version.json`.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use url::Url;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{fetch_file, find_references, Detection, FrameworkDetector, Page};
+
+const IMMUTABLE_DIR: &str = "_app/immutable/";
+
+#[derive(Deserialize)]
+struct AppVersion {
+    version: String,
+}
+
+/// Detects SvelteKit apps, listing the entry modules and chunks the page
+/// refers to. The app version is reported as the build.
+///
+/// SvelteKit does not publish its own version, so none is reported.
+pub struct SvelteKitDetector;
+
+#[async_trait]
+impl FrameworkDetector for SvelteKitDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        let chunks: Vec<Url> = find_references(page.body, IMMUTABLE_DIR)
+            .iter()
+            .filter_map(|r| page.url.join(r).ok())
+            .collect();
+        if chunks.is_empty() && !page.body.contains("__sveltekit") {
+            return None;
+        }
+
+        // the app directory sits below the base path of the app
+        let app_dir = chunks
+            .iter()
+            .find_map(|c| {
+                let idx = c.path().find(IMMUTABLE_DIR)?;
+                c.join(&c.path()[..idx + "_app/".len()]).ok()
+            })
+            .or_else(|| page.url.join("/_app/").ok())?;
+        let build = match app_dir.join("version.json") {
+            Ok(url) => fetch_file(page, client, &url, errors)
+                .await
+                .and_then(|resp| serde_json::from_str::<AppVersion>(&resp.body).ok())
+                .map(|v| v.version),
+            Err(_) => None,
+        };
+
+        let mut detection = Detection::new("sveltekit").with_build(build);
+        detection.add_chunks(chunks);
+        Some(detection)
+    }
+}
@@ -0,0 +1,173 @@
+//! Reading the build manifests of Vite and Rollup apps.
+//!
+//! With `build.manifest` enabled Vite writes `.vite/manifest.json`, older
+//! versions `manifest.json`, into the output directory. When it is deployed
+//! along with the assets it lists every chunk of the app, including those
+//! only loaded on demand.
+
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use url::Url;
+
+use crate::client::ParsesmClient;
+use crate::error::ParsesmError;
+use crate::framework::{fetch_in_scope, Detection, FrameworkDetector, Page};
+
+/// Manifest locations relative to the root of the build output.
+const MANIFEST_PATHS: [&str; 2] = [".vite/manifest.json", "manifest.json"];
+
+/// Whether the page loads ES modules, as Vite and Rollup builds do.
+pub fn is_module_page(body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    body.contains("modulepreload")
+        || body.contains("type=\"module\"")
+        || body.contains("type='module'")
+        || body.contains("type=module")
+}
+
+/// Returns the root of the build output a page's scripts were served from.
+///
+/// Vite puts chunks into `assets/` below the root of the output, so the root
+/// is taken from the first script in such a directory, falling back to the
+/// root of the site.
+pub fn build_root(page_url: &Url, scripts: &[Url]) -> Option<Url> {
+    scripts
+        .iter()
+        .find_map(|s| {
+            let idx = s.path().find("/assets/")?;
+            s.join(&s.path()[..idx + 1]).ok()
+        })
+        .or_else(|| page_url.join("/").ok())
+}
+
+/// Returns the urls the manifest may be served at below the build root, in
+/// the order they should be tried.
+pub fn manifest_urls(root: &Url) -> Vec<Url> {
+    MANIFEST_PATHS
+        .iter()
+        .filter_map(|path| root.join(path).ok())
+        .collect()
+}
+
+/// Parses a manifest, returning the script files it lists relative to the
+/// root of the build.
+///
+/// Vite manifests map each source to an entry with its `file`, Rollup
+/// manifest plugins map chunk names straight to files. Json that is neither,
+/// such as a web app manifest at the same url, yields nothing.
+pub fn parse_manifest(body: &str) -> Vec<String> {
+    let entries = match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(entries)) => entries,
+        _ => return vec![],
+    };
+
+    let mut seen = HashSet::new();
+    let mut files = vec![];
+    for entry in entries.values() {
+        let file = match entry {
+            Value::String(file) => file,
+            Value::Object(chunk) => match chunk.get("file") {
+                Some(Value::String(file)) => file,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let is_script = [".js", ".mjs", ".cjs"]
+            .iter()
+            .any(|ext| file.ends_with(ext));
+        if is_script && seen.insert(file.clone()) {
+            files.push(file.clone());
+        }
+    }
+    files
+}
+
+/// Detects Vite and Rollup builds that deploy their manifest, listing every
+/// chunk in it.
+///
+/// Builds without a public manifest are still followed through their
+/// imports, see [`crate::esm`].
+pub struct ViteDetector;
+
+#[async_trait]
+impl FrameworkDetector for ViteDetector {
+    async fn detect(
+        &self,
+        page: &Page<'_>,
+        client: &ParsesmClient,
+        errors: &mut Vec<ParsesmError>,
+    ) -> Option<Detection> {
+        if !is_module_page(page.body) {
+            return None;
+        }
+        let root = build_root(page.url, page.scripts)?;
+
+        for manifest_url in manifest_urls(&root) {
+            let files = match fetch_in_scope(page, client, &manifest_url).await {
+                None => continue,
+                Some(Ok(resp)) => parse_manifest(&resp.body),
+                // most builds do not deploy their manifest
+                Some(Err(ParsesmError::HttpStatus { .. })) => continue,
+                Some(Err(e)) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if files.is_empty() {
+                continue;
+            }
+
+            let mut detection = Detection::new("vite");
+            detection.add_chunks(files.iter().filter_map(|f| root.join(f).ok()));
+            return Some(detection);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_module_pages() {
+        assert!(is_module_page(
+            r#"<script type="module" src="/a.js"></script>"#
+        ));
+        assert!(is_module_page(r#"<link rel="modulepreload" href="/a.js">"#));
+        assert!(!is_module_page(r#"<script src="/a.js"></script>"#));
+    }
+
+    #[test]
+    fn build_root_is_above_assets() {
+        let page = Url::parse("https://a.com/app/").unwrap();
+        let scripts = [Url::parse("https://a.com/app/assets/index-1a2b.js").unwrap()];
+        let root = build_root(&page, &scripts).unwrap();
+        assert_eq!(root.as_str(), "https://a.com/app/");
+        assert_eq!(
+            manifest_urls(&root)[0].as_str(),
+            "https://a.com/app/.vite/manifest.json"
+        );
+        assert_eq!(build_root(&page, &[]).unwrap().as_str(), "https://a.com/");
+    }
+
+    #[test]
+    fn parses_vite_and_rollup_manifests() {
+        let vite = r#"{
+            "index.html": {"file": "assets/index-1a2b.js", "css": ["assets/index.css"]},
+            "src/lazy.ts": {"file": "assets/lazy-3c4d.js", "isDynamicEntry": true},
+            "style.css": {"file": "assets/style.css"}
+        }"#;
+        assert_eq!(
+            parse_manifest(vite),
+            ["assets/index-1a2b.js", "assets/lazy-3c4d.js"]
+        );
+        assert_eq!(
+            parse_manifest(r#"{"main": "main-5e6f.js"}"#),
+            ["main-5e6f.js"]
+        );
+        assert!(parse_manifest(r#"{"name": "My App", "icons": []}"#).is_empty());
+    }
+}
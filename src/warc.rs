@@ -0,0 +1,608 @@
+//! Archiving traffic in WARC files and replaying archives.
+//!
+//! A [`WarcWriter`] attached to an [`HttpFetcher`](crate::HttpFetcher) keeps
+//! a request and a response record for every exchange, so recovered sources
+//! can always be traced back to what was served. [`read_warc`] loads an
+//! archive, e.g. from a prior crawl, for a
+//! [`ReplayFetcher`](crate::replay::ReplayFetcher).
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::{GzDecoder, MultiGzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, TRANSFER_ENCODING};
+use http::{StatusCode, Version};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::error::ParsesmError;
+use crate::fetch::Headers;
+use crate::replay::Capture;
+
+const WARC_VERSION: &str = "WARC/1.1";
+
+/// One http request and the response it got.
+pub(crate) struct HttpExchange<'a> {
+    /// Url of the request.
+    pub(crate) url: &'a Url,
+    /// Headers sent besides `host` and `accept`.
+    pub(crate) request_headers: &'a HeaderMap,
+    /// Http version of the response.
+    pub(crate) version: Version,
+    /// Status of the response.
+    pub(crate) status: u16,
+    /// Headers of the response.
+    pub(crate) response_headers: &'a HeaderMap,
+    /// Body of the response exactly as it was received.
+    pub(crate) body: &'a [u8],
+}
+
+/// Appends request and response records to a WARC file.
+///
+/// Files ending in `.gz` get every record compressed on its own, as tools
+/// reading `.warc.gz` archives expect. Records are written as soon as an
+/// exchange completes, so an interrupted scan still leaves a valid archive.
+#[derive(Debug)]
+pub struct WarcWriter {
+    path: PathBuf,
+    gzip: bool,
+    file: Mutex<File>,
+    records: AtomicU64,
+}
+
+impl WarcWriter {
+    /// Creates the archive at `path`, replacing any previous one, and writes
+    /// its `warcinfo` record.
+    pub fn create<P: Into<PathBuf>>(path: P) -> Result<Self, ParsesmError> {
+        let path = path.into();
+        let file = File::create(&path).map_err(|source| ParsesmError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let writer = Self {
+            gzip: path.extension().map(|e| e == "gz").unwrap_or(false),
+            path,
+            file: Mutex::new(file),
+            records: AtomicU64::new(0),
+        };
+
+        let info = format!(
+            "software: {}/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let filename = writer
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        writer.write_record(
+            &[
+                ("WARC-Type", "warcinfo".to_owned()),
+                ("WARC-Record-ID", writer.record_id()),
+                ("WARC-Date", warc_date(SystemTime::now())),
+                ("WARC-Filename", filename),
+                ("Content-Type", "application/warc-fields".to_owned()),
+            ],
+            info.as_bytes(),
+        )?;
+
+        Ok(writer)
+    }
+
+    /// Writes the records of one exchange.
+    ///
+    /// The request is rebuilt from what the client sends, the response keeps
+    /// the status line, headers and body as received. A chunked body is
+    /// written as a single chunk so its headers still describe it.
+    pub(crate) fn record(&self, exchange: &HttpExchange) -> Result<(), ParsesmError> {
+        let url = exchange.url;
+        let version = format!("{:?}", exchange.version);
+
+        let mut request = format!(
+            "GET {}",
+            &url[url::Position::BeforePath..url::Position::AfterQuery]
+        );
+        request.push_str(&format!(" {}\r\n", version));
+        let host = &url[url::Position::BeforeHost..url::Position::AfterPort];
+        request.push_str(&format!("host: {}\r\naccept: */*\r\n", host));
+        push_headers(&mut request, exchange.request_headers);
+        request.push_str("\r\n");
+
+        let reason = StatusCode::from_u16(exchange.status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or_default();
+        let mut head = format!("{} {} {}\r\n", version, exchange.status, reason);
+        push_headers(&mut head, exchange.response_headers);
+        head.push_str("\r\n");
+        let mut response = head.into_bytes();
+        if is_chunked(exchange.response_headers) {
+            response.extend(format!("{:x}\r\n", exchange.body.len()).as_bytes());
+            response.extend(exchange.body);
+            response.extend(b"\r\n0\r\n\r\n");
+        } else {
+            response.extend(exchange.body);
+        }
+
+        let date = warc_date(SystemTime::now());
+        let response_id = self.record_id();
+        self.write_record(
+            &[
+                ("WARC-Type", "response".to_owned()),
+                ("WARC-Record-ID", response_id.clone()),
+                ("WARC-Date", date.clone()),
+                ("WARC-Target-URI", url.to_string()),
+                ("WARC-Payload-Digest", digest(exchange.body)),
+                ("WARC-Block-Digest", digest(&response)),
+                (
+                    "Content-Type",
+                    "application/http;msgtype=response".to_owned(),
+                ),
+            ],
+            &response,
+        )?;
+        self.write_record(
+            &[
+                ("WARC-Type", "request".to_owned()),
+                ("WARC-Record-ID", self.record_id()),
+                ("WARC-Date", date),
+                ("WARC-Target-URI", url.to_string()),
+                ("WARC-Concurrent-To", response_id),
+                ("WARC-Block-Digest", digest(request.as_bytes())),
+                (
+                    "Content-Type",
+                    "application/http;msgtype=request".to_owned(),
+                ),
+            ],
+            request.as_bytes(),
+        )
+    }
+
+    fn write_record(&self, headers: &[(&str, String)], block: &[u8]) -> Result<(), ParsesmError> {
+        let mut record = format!("{}\r\n", WARC_VERSION);
+        for (name, value) in headers {
+            record.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        record.push_str(&format!("Content-Length: {}\r\n\r\n", block.len()));
+        let mut record = record.into_bytes();
+        record.extend(block);
+        record.extend(b"\r\n\r\n");
+
+        let io_error = |source| ParsesmError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        if self.gzip {
+            let mut gz = GzEncoder::new(vec![], Compression::default());
+            gz.write_all(&record).map_err(io_error)?;
+            record = gz.finish().map_err(io_error)?;
+        }
+        // one write per record keeps records of concurrent requests apart
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&record).map_err(io_error)?;
+        file.flush().map_err(io_error)
+    }
+
+    /// Returns a new record id, unique within the process and practically
+    /// unique across runs.
+    fn record_id(&self) -> String {
+        let count = self.records.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let seed = format!(
+            "{}:{}:{}:{}",
+            self.path.display(),
+            std::process::id(),
+            nanos,
+            count
+        );
+        let hash = hex::encode(Sha256::digest(seed.as_bytes()));
+        format!(
+            "<urn:uuid:{}-{}-{}-{}-{}>",
+            &hash[..8],
+            &hash[8..12],
+            &hash[12..16],
+            &hash[16..20],
+            &hash[20..32]
+        )
+    }
+}
+
+/// Reads a WARC file, compressed or not.
+///
+/// `response` records are replayed with their http status and headers,
+/// `resource` records as plain 200 responses. Other records are skipped.
+/// Records are read one at a time, so only the replayed responses are kept
+/// in memory.
+pub fn read_warc(path: &Path) -> Result<Capture, ParsesmError> {
+    let io_error = |source| ParsesmError::Io {
+        path: path.to_owned(),
+        source,
+    };
+
+    let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+    let gzip = reader
+        .fill_buf()
+        .map_err(io_error)?
+        .starts_with(&[0x1f, 0x8b]);
+    let capture = if gzip {
+        read_records(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        read_records(reader)
+    };
+
+    capture.map_err(io_error)?.ok_or_else(|| {
+        io_error(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "not a warc file",
+        ))
+    })
+}
+
+/// Reads uncompressed WARC records, skipping over the blocks of records that
+/// are not replayed. Returns `None` if the data does not start with a record,
+/// a damaged record ends the capture early.
+fn read_records<R: BufRead>(mut reader: R) -> std::io::Result<Option<Capture>> {
+    let mut capture = Capture::default();
+    let mut records = 0;
+
+    loop {
+        // records end with a blank line
+        let mut line = vec![];
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(Some(capture));
+            }
+            if line != b"\r\n" && line != b"\n" {
+                break;
+            }
+        }
+
+        let head = match read_head(&line, &mut reader)? {
+            Some(head) => head,
+            None if records == 0 => return Ok(None),
+            None => break,
+        };
+        records += 1;
+
+        let url = head.target_uri.as_deref().and_then(|u| Url::parse(u).ok());
+        let url = match url {
+            Some(url) if head.warc_type == "response" || head.warc_type == "resource" => url,
+            _ => {
+                let skipped =
+                    std::io::copy(&mut reader.by_ref().take(head.length), &mut std::io::sink())?;
+                if skipped < head.length {
+                    break;
+                }
+                continue;
+            }
+        };
+        let mut block = vec![];
+        reader.by_ref().take(head.length).read_to_end(&mut block)?;
+        if (block.len() as u64) < head.length {
+            break;
+        }
+
+        if head.warc_type == "response" {
+            if let Some((status, headers, body)) = parse_http_response(&block) {
+                capture.insert(url, status, Headers(headers), body);
+            }
+        } else {
+            let mut headers = HeaderMap::new();
+            if let Some(value) = head
+                .content_type
+                .and_then(|v| HeaderValue::from_str(&v).ok())
+            {
+                headers.insert(http::header::CONTENT_TYPE, value);
+            }
+            let body = String::from_utf8_lossy(&block).into_owned();
+            capture.insert(url, 200, Headers(headers), Some(body));
+        }
+    }
+
+    Ok(Some(capture))
+}
+
+struct RecordHead {
+    warc_type: String,
+    target_uri: Option<String>,
+    content_type: Option<String>,
+    length: u64,
+}
+
+/// Reads the fields of a record after its `first_line`, leaving the reader at
+/// the start of its block. Returns `None` for anything but a complete head.
+fn read_head<R: BufRead>(first_line: &[u8], reader: &mut R) -> std::io::Result<Option<RecordHead>> {
+    if !first_line.starts_with(b"WARC/") {
+        return Ok(None);
+    }
+
+    let mut head = RecordHead {
+        warc_type: String::new(),
+        target_uri: None,
+        content_type: None,
+        length: 0,
+    };
+    let mut length = None;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = match std::str::from_utf8(&line) {
+            Ok(line) => line.trim_end_matches(['\r', '\n']),
+            Err(_) => return Ok(None),
+        };
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "warc-type" => head.warc_type = value.to_owned(),
+            // warc 1.0 drafts wrapped the uri in angle brackets
+            "warc-target-uri" => head.target_uri = Some(value.trim_matches(['<', '>']).to_owned()),
+            "content-type" => head.content_type = Some(value.to_owned()),
+            "content-length" => length = value.parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+
+    Ok(length.map(|length| RecordHead { length, ..head }))
+}
+
+/// Splits an http response into status, headers and decoded body. The body
+/// is `None` when its encoding is not supported.
+fn parse_http_response(block: &[u8]) -> Option<(u16, HeaderMap, Option<String>)> {
+    let head_end = find(block, b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&block[..head_end]);
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.trim().as_bytes()),
+                HeaderValue::from_str(value.trim()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    let mut body = block[head_end + 4..].to_vec();
+    if is_chunked(&headers) {
+        body = dechunk(&body)?;
+    }
+    let encoding = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let body = match encoding.as_str() {
+        "" | "identity" => Some(body),
+        "gzip" | "x-gzip" => read_all(GzDecoder::new(&body[..])),
+        "deflate" => read_all(ZlibDecoder::new(&body[..])),
+        _ => None,
+    };
+
+    Some((
+        status,
+        headers,
+        body.map(|b| String::from_utf8_lossy(&b).into_owned()),
+    ))
+}
+
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line_end = find(data, b"\r\n")?;
+        let line = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size = line.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend(data.get(..size)?);
+        data = data
+            .get(size..)?
+            .strip_prefix(b"\r\n")
+            .unwrap_or(&data[size..]);
+    }
+}
+
+fn read_all<R: Read>(mut reader: R) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    reader.read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+fn is_chunked(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(TRANSFER_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.to_ascii_lowercase().contains("chunked"))
+}
+
+fn push_headers(out: &mut String, headers: &HeaderMap) {
+    for (name, value) in headers {
+        out.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Returns a labelled sha256 digest in the base32 form warc tools use.
+fn digest(bytes: &[u8]) -> String {
+    format!("sha256:{}", base32(&Sha256::digest(bytes)))
+}
+
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    while !out.len().is_multiple_of(8) {
+        out.push('=');
+    }
+    out
+}
+
+/// Formats a time as the utc timestamp warc headers use.
+fn warc_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // civil date from days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::Fetcher;
+    use crate::replay::ReplayFetcher;
+
+    async fn round_trip(file_name: &str) {
+        let path =
+            std::env::temp_dir().join(format!("parsesm-{}-{}", std::process::id(), file_name));
+        let writer = WarcWriter::create(&path).unwrap();
+
+        let script = Url::parse("https://a.com/app.js").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        writer
+            .record(&HttpExchange {
+                url: &script,
+                request_headers: &HeaderMap::new(),
+                version: Version::HTTP_11,
+                status: 200,
+                response_headers: &headers,
+                body: b"console.log(1)\n//# sourceMappingURL=app.js.map\n",
+            })
+            .unwrap();
+        let missing = Url::parse("https://a.com/app.js.map").unwrap();
+        writer
+            .record(&HttpExchange {
+                url: &missing,
+                request_headers: &HeaderMap::new(),
+                version: Version::HTTP_2,
+                status: 404,
+                response_headers: &HeaderMap::new(),
+                body: b"",
+            })
+            .unwrap();
+        drop(writer);
+
+        let capture = read_warc(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(capture.len(), 2);
+
+        let fetcher = ReplayFetcher::new(capture);
+        let resp = fetcher.get(&script).await.unwrap();
+        assert_eq!(resp.status, 200);
+        assert!(resp.body.ends_with("sourceMappingURL=app.js.map\n"));
+        assert_eq!(fetcher.get(&missing).await.unwrap().status, 404);
+    }
+
+    #[tokio::test]
+    async fn plain_round_trip() {
+        round_trip("plain.warc").await;
+    }
+
+    #[tokio::test]
+    async fn gzip_round_trip() {
+        round_trip("gzip.warc.gz").await;
+    }
+
+    #[test]
+    fn skips_other_records_and_stops_at_damage() {
+        let record = |kind: &str, uri: &str, block: &str| {
+            format!(
+                "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Target-URI: <{}>\r\nContent-Length: {}\r\n\r\n{}\r\n\r\n",
+                kind,
+                uri,
+                block.len(),
+                block
+            )
+        };
+        let data = [
+            record("request", "https://a.com/app.js", "GET /app.js HTTP/1.1\r\n\r\n"),
+            record("resource", "https://a.com/app.js", "console.log(1)"),
+            record("metadata", "https://a.com/app.js", "via: crawler"),
+            "WARC/1.1\r\nWARC-Type: resource\r\nWARC-Target-URI: https://a.com/b.js\r\nContent-Length: 99\r\n\r\ncut".to_owned(),
+        ]
+        .concat();
+
+        let capture = read_records(data.as_bytes()).unwrap().unwrap();
+        assert_eq!(capture.len(), 1);
+        assert!(read_records(&b"GET / HTTP/1.1\r\n\r\n"[..])
+            .unwrap()
+            .is_none());
+        assert_eq!(read_records(&b""[..]).unwrap().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn digests_are_base32() {
+        assert_eq!(
+            digest(b""),
+            "sha256:4OYMIQUY7QOBJGX36TEJS35ZEQT24QPEMSNZGTFESWMRW6CSXBKQ===="
+        );
+    }
+}
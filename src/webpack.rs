@@ -0,0 +1,322 @@
+//! Rebuilding the urls of chunks webpack loads lazily.
+//!
+//! Code split apps only reference their entry scripts in the html. Every
+//! other chunk is loaded by the webpack runtime, which builds the file name
+//! from tables mapping chunk ids to names and content hashes, e.g.
+//!
+//! ```js
+//! r.u = e => "static/chunks/" + ({296: "about"}[e] || e) + "." + {296: "3f6a0d1c", 412: "9b2e4410"}[e] + ".js"
+//! ```
+//!
+//! [`find_runtime`] reads those tables back from the runtime, or from the
+//! entry bundle it is inlined in, without executing anything.
+
+use std::collections::{HashMap, HashSet};
+
+use url::Url;
+
+use crate::js::{
+    expression_end, is_ident_char, parse_string, split_top_level, strip_keyword, strip_parens,
+};
+
+const SCRIPT_SUFFIXES: [&str; 3] = [".js", ".mjs", ".cjs"];
+
+/// Longest chunk filename expression considered, bounding the work spent on
+/// functions that merely look like one.
+const MAX_EXPRESSION_LEN: usize = 1 << 20;
+
+/// The chunk files a webpack runtime can load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WebpackRuntime {
+    /// The `publicPath` chunks are loaded from, `None` when it is computed
+    /// at runtime.
+    pub public_path: Option<String>,
+    /// File names of the chunks, relative to the public path.
+    pub chunks: Vec<String>,
+}
+
+impl WebpackRuntime {
+    /// Resolves the chunk files for a runtime found in `script_url`.
+    ///
+    /// A literal public path is relative to the page like any other script
+    /// url, `document_base` is the page url or its `<base href>`, see
+    /// [`document_base`](crate::discovery::document_base). Without one
+    /// webpack derives it from the script, so chunks are resolved against the
+    /// script's directory.
+    pub fn chunk_urls(&self, document_base: &Url, script_url: &Url) -> Vec<Url> {
+        let base = match &self.public_path {
+            Some(public_path) => match document_base.join(public_path) {
+                Ok(base) => base,
+                Err(_) => return vec![],
+            },
+            None => script_url.clone(),
+        };
+
+        self.chunks
+            .iter()
+            .filter_map(|chunk| base.join(chunk).ok())
+            .collect()
+    }
+}
+
+/// Finds the chunk filename function of a webpack runtime in a script.
+///
+/// Both the webpack 5 `__webpack_require__.u` function and the webpack 4
+/// `jsonpScriptSrc` function are recognized, minified or not. Returns `None`
+/// when the script has no function concatenating chunk ids with a table of
+/// names or hashes into a script file name.
+pub fn find_runtime(body: &str) -> Option<WebpackRuntime> {
+    let mut seen = HashSet::new();
+    let mut chunks = vec![];
+    for start in function_starts(body) {
+        let files =
+            parse_function(&body[start..]).and_then(|(param, expr)| chunk_files(param, expr));
+        for file in files.into_iter().flatten() {
+            if seen.insert(file.clone()) {
+                chunks.push(file);
+            }
+        }
+    }
+
+    if chunks.is_empty() {
+        return None;
+    }
+    Some(WebpackRuntime {
+        public_path: find_public_path(body),
+        chunks,
+    })
+}
+
+/// Returns the literal assigned to `__webpack_require__.p`, whatever the
+/// runtime object was minified to.
+fn find_public_path(body: &str) -> Option<String> {
+    for (idx, _) in body.match_indices(".p") {
+        let rest = &body[idx + 2..];
+        if rest.starts_with(is_ident_char) {
+            continue;
+        }
+        let rest = match rest.trim_start().strip_prefix('=') {
+            Some(rest) if !rest.starts_with(['=', '>']) => rest.trim_start(),
+            _ => continue,
+        };
+        let path = parse_string(rest[..expression_end(rest)].trim());
+        if let Some(path) = path.filter(|p| p.is_empty() || p.ends_with('/')) {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// Returns the offsets of everything that may be a chunk filename function:
+/// values assigned to `.u` and function expressions.
+fn function_starts(body: &str) -> Vec<usize> {
+    let mut starts = vec![];
+    for (idx, _) in body.match_indices(".u") {
+        let rest = &body[idx + 2..];
+        let trimmed = rest.trim_start();
+        if let Some(value) = trimmed.strip_prefix('=') {
+            if !value.starts_with(['=', '>']) {
+                starts.push(body.len() - value.len());
+            }
+        }
+    }
+    for (idx, _) in body.match_indices("function") {
+        if !body[..idx].ends_with(is_ident_char) {
+            starts.push(idx);
+        }
+    }
+    starts
+}
+
+/// Splits a single parameter function returning an expression into the
+/// parameter and the expression.
+fn parse_function(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    let (param, body) = if let Some(rest) = strip_keyword(s, "function") {
+        let rest = rest.trim_start().trim_start_matches(is_ident_char);
+        let (param, rest) = rest.trim_start().strip_prefix('(')?.split_once(')')?;
+        let rest = rest.trim_start().strip_prefix('{')?;
+        (param, strip_keyword(rest.trim_start(), "return")?)
+    } else {
+        let (param, rest) = match s.strip_prefix('(') {
+            Some(rest) => rest.split_once(')')?,
+            None => s.split_at(s.find(|c| !is_ident_char(c))?),
+        };
+        let rest = rest.trim_start().strip_prefix("=>")?.trim_start();
+        let rest = match rest.strip_prefix('{') {
+            Some(block) => strip_keyword(block.trim_start(), "return")?,
+            None => rest,
+        };
+        (param, rest)
+    };
+
+    let param = param.trim();
+    if param.is_empty() || !param.chars().all(is_ident_char) {
+        return None;
+    }
+    // chunk names start with a string, the chunk id, a table lookup or the
+    // public path
+    let body = body.trim_start();
+    let first = &body[..body.find(|c: char| !is_ident_char(c)).unwrap_or(body.len())];
+    let plausible = body.starts_with(['"', '\'', '(', '{'])
+        || first == param
+        || (!first.is_empty() && body[first.len()..].starts_with(".p"));
+    if !plausible {
+        return None;
+    }
+
+    let mut limit = body.len().min(MAX_EXPRESSION_LEN);
+    while !body.is_char_boundary(limit) {
+        limit -= 1;
+    }
+    let body = &body[..limit];
+    Some((param, &body[..expression_end(body)]))
+}
+
+/// A term of a chunk file name concatenation.
+enum Term {
+    Literal(String),
+    ChunkId,
+    /// `{id: value}[chunkId]`, chunks missing from the table have no file.
+    Table(HashMap<String, String>),
+    /// `({id: value}[chunkId] || chunkId)`
+    TableOrId(HashMap<String, String>),
+}
+
+/// Evaluates a chunk file name expression for every chunk id in its tables.
+fn chunk_files(param: &str, expr: &str) -> Option<Vec<String>> {
+    let mut ids = vec![];
+    let mut terms = vec![];
+    for term in split_top_level(expr, '+') {
+        let term = strip_parens(term.trim());
+        if let Some(literal) = parse_string(term) {
+            terms.push(Term::Literal(literal));
+        } else if term == param {
+            terms.push(Term::ChunkId);
+        } else if is_public_path(term) {
+            // webpack 4 prefixes the public path itself
+        } else if let Some(table) = term
+            .strip_suffix(param)
+            .and_then(|t| t.trim_end().strip_suffix("||"))
+        {
+            terms.push(Term::TableOrId(parse_lookup(table, param, &mut ids)?));
+        } else {
+            terms.push(Term::Table(parse_lookup(term, param, &mut ids)?));
+        }
+    }
+
+    let is_script = matches!(
+        terms.last(),
+        Some(Term::Literal(suffix)) if SCRIPT_SUFFIXES.iter().any(|s| suffix.ends_with(s))
+    );
+    if ids.is_empty() || !is_script {
+        return None;
+    }
+
+    let files = ids
+        .iter()
+        .filter_map(|id| {
+            terms
+                .iter()
+                .map(|term| match term {
+                    Term::Literal(literal) => Some(literal.as_str()),
+                    Term::ChunkId => Some(id.as_str()),
+                    Term::Table(table) => table.get(id).map(String::as_str),
+                    Term::TableOrId(table) => Some(table.get(id).unwrap_or(id).as_str()),
+                })
+                .collect::<Option<String>>()
+        })
+        .collect();
+    Some(files)
+}
+
+/// Parses `{id: "value", ...}[param]`, adding new chunk ids to `ids`.
+fn parse_lookup(term: &str, param: &str, ids: &mut Vec<String>) -> Option<HashMap<String, String>> {
+    let term = strip_parens(term.trim());
+    let (object, key) = term.strip_suffix(']')?.rsplit_once('[')?;
+    if key.trim() != param {
+        return None;
+    }
+    let object = strip_parens(object.trim());
+    let entries = object.strip_prefix('{')?.strip_suffix('}')?;
+
+    let mut table = HashMap::new();
+    for entry in split_top_level(entries, ',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = match split_top_level(entry, ':')[..] {
+            [key, value] => (key.trim(), parse_string(value.trim())?),
+            _ => return None,
+        };
+        let key = match parse_string(key) {
+            Some(key) => key,
+            None if !key.is_empty() && key.chars().all(is_ident_char) => key.to_owned(),
+            None => return None,
+        };
+        if !ids.contains(&key) {
+            ids.push(key.clone());
+        }
+        table.insert(key, value);
+    }
+    Some(table)
+}
+
+/// Whether a term is the public path, `__webpack_require__.p` or a minified
+/// `r.p`.
+fn is_public_path(term: &str) -> bool {
+    term.strip_suffix(".p")
+        .map(|object| !object.is_empty() && object.chars().all(is_ident_char))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webpack5_runtime() {
+        let body = r#"r.p="/_next/";r.u=e=>"static/chunks/"+({296:"about"}[e]||e)+"."+{296:"3f6a0d1c",412:"9b2e4410"}[e]+".js";"#;
+        let runtime = find_runtime(body).unwrap();
+        assert_eq!(runtime.public_path.as_deref(), Some("/_next/"));
+        assert_eq!(
+            runtime.chunks,
+            [
+                "static/chunks/about.3f6a0d1c.js",
+                "static/chunks/412.9b2e4410.js"
+            ]
+        );
+
+        let page = Url::parse("https://a.com/blog/post").unwrap();
+        let script = Url::parse("https://cdn.com/main.js").unwrap();
+        assert_eq!(
+            runtime.chunk_urls(&page, &script)[0].as_str(),
+            "https://a.com/_next/static/chunks/about.3f6a0d1c.js"
+        );
+    }
+
+    #[test]
+    fn webpack4_runtime() {
+        let body = r#"function jsonpScriptSrc(chunkId) {
+            return __webpack_require__.p + "" + ({"0":"vendor"}[chunkId]||chunkId) + "." + {"0":"abc","1":"def"}[chunkId] + ".chunk.js"
+        }"#;
+        let runtime = find_runtime(body).unwrap();
+        assert_eq!(runtime.public_path, None);
+        assert_eq!(runtime.chunks, ["vendor.abc.chunk.js", "1.def.chunk.js"]);
+
+        let page = Url::parse("https://a.com/").unwrap();
+        let script = Url::parse("https://a.com/static/js/main.js").unwrap();
+        assert_eq!(
+            runtime.chunk_urls(&page, &script)[1].as_str(),
+            "https://a.com/static/js/1.def.chunk.js"
+        );
+    }
+
+    #[test]
+    fn ignores_other_functions() {
+        assert_eq!(find_runtime("function f(e){return e+1}"), None);
+        assert_eq!(find_runtime(r#"a.u=e=>"x"+e+".css""#), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Writing recovered sources into the output of a target.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::ParsesmError;
+use crate::manifest::{sha256_hex, MANIFEST_FILE};
+use crate::paths::sanitize_source_path;
+use crate::sink::Sink;
+
+/// What to do when a source is written to a path that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file.
+    Skip,
+    /// Keep identical files once and write differing content side by side as
+    /// `name~<hash>.ext`.
+    #[default]
+    Version,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "version" => Ok(ConflictPolicy::Version),
+            _ => Err(format!(
+                "invalid conflict policy `{}`, expected overwrite, skip or version",
+                s
+            )),
+        }
+    }
+}
+
+/// Counters for the sources handled by a [`SourceWriter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WriteStats {
+    /// Sources written, including overwrites and versioned copies.
+    pub written: usize,
+    /// Sources whose content was identical to the file already written.
+    pub duplicates: usize,
+    /// Sources whose content differed from the file already written.
+    pub conflicts: usize,
+}
+
+/// Where a source ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The source was written to this path.
+    Written(PathBuf),
+    /// A file with identical content already exists at this path.
+    Duplicate(PathBuf),
+    /// The source conflicted with an existing file and was not written.
+    Skipped,
+}
+
+/// Writes the recovered sources of a target into a [`Sink`].
+pub struct SourceWriter {
+    sink: Box<dyn Sink>,
+    policy: ConflictPolicy,
+    stats: WriteStats,
+    warnings: Vec<ParsesmError>,
+}
+
+impl SourceWriter {
+    /// Creates a writer storing sources in `sink`.
+    pub fn new(sink: Box<dyn Sink>, policy: ConflictPolicy) -> Self {
+        Self {
+            sink,
+            policy,
+            stats: WriteStats::default(),
+            warnings: vec![],
+        }
+    }
+
+    /// Describes where the sources end up.
+    pub fn location(&self) -> String {
+        self.sink.location()
+    }
+
+    /// Counts of what was written so far.
+    pub fn stats(&self) -> WriteStats {
+        self.stats
+    }
+
+    /// Returns the problems with sources that were written anyway, e.g. a
+    /// path clamped to the output directory, since the last call.
+    pub fn take_warnings(&mut self) -> Vec<ParsesmError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Returns the sink, e.g. to add the manifest and finish it.
+    pub fn into_sink(self) -> Box<dyn Sink> {
+        self.sink
+    }
+
+    /// Writes a recovered source.
+    ///
+    /// Paths are derived from untrusted source names, so they are sanitized
+    /// first. A path climbing above the output directory is clamped to it and
+    /// kept as a path safety warning, see [`SourceWriter::take_warnings`].
+    /// Sources that would still land outside the output directory are
+    /// rejected with a path safety error. Returned paths are relative to the
+    /// sink.
+    pub fn write(&mut self, path: &str, contents: &str) -> Result<WriteOutcome, ParsesmError> {
+        let sanitized = sanitize_source_path(path).ok_or_else(|| ParsesmError::PathSafety {
+            name: path.to_owned(),
+            reason: "no usable path is left after sanitizing".to_owned(),
+        })?;
+        if sanitized.clamped {
+            self.warnings.push(ParsesmError::PathSafety {
+                name: path.to_owned(),
+                reason: "it climbs above the output directory, written below it instead".to_owned(),
+            });
+        }
+        let mut target = sanitized.path;
+        // the manifest lives next to the sources so its name is reserved
+        if target == Path::new(MANIFEST_FILE) {
+            target = PathBuf::from(format!("{}_", MANIFEST_FILE));
+        }
+
+        if let Some(existing) = self.sink.existing(&target)? {
+            let sha256 = sha256_hex(contents.as_bytes());
+            if existing == sha256 {
+                self.stats.duplicates += 1;
+                return Ok(WriteOutcome::Duplicate(target));
+            }
+
+            match self.policy {
+                ConflictPolicy::Overwrite => self.stats.conflicts += 1,
+                ConflictPolicy::Skip => {
+                    self.stats.conflicts += 1;
+                    return Ok(WriteOutcome::Skipped);
+                }
+                ConflictPolicy::Version => {
+                    target = versioned_path(&target, &sha256);
+                    // the hash is in the name so an existing file has this content
+                    if self.sink.existing(&target)?.is_some() {
+                        self.stats.duplicates += 1;
+                        return Ok(WriteOutcome::Duplicate(target));
+                    }
+                    self.stats.conflicts += 1;
+                }
+            }
+        }
+
+        self.sink.write(&target, contents.as_bytes())?;
+        self.stats.written += 1;
+
+        Ok(WriteOutcome::Written(target))
+    }
+}
+
+/// Returns `dir/name~<hash>.ext` for `dir/name.ext`.
+fn versioned_path(path: &Path, sha256: &str) -> PathBuf {
+    let hash = &sha256[..8];
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}~{}.{}", stem, hash, ext),
+        _ => format!("{}~{}", file_name, hash),
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+
+    /// Writes `a` twice and then `b` twice to the same path, returning the
+    /// stats and the files left in the sink.
+    fn write_twice(policy: ConflictPolicy) -> (WriteStats, Vec<(String, String)>) {
+        let sink = MemorySink::new();
+        let mut writer = SourceWriter::new(Box::new(sink.clone()), policy);
+        for contents in ["a", "a", "b", "b"] {
+            writer.write("src/a.js", contents).unwrap();
+        }
+
+        let files = sink
+            .files()
+            .into_iter()
+            .map(|(path, contents)| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, String::from_utf8(contents).unwrap())
+            })
+            .collect();
+        (writer.stats(), files)
+    }
+
+    fn file(name: &str, contents: &str) -> (String, String) {
+        (name.to_owned(), contents.to_owned())
+    }
+
+    #[test]
+    fn overwrite_replaces_differing_files() {
+        let (stats, files) = write_twice(ConflictPolicy::Overwrite);
+        assert_eq!(
+            stats,
+            WriteStats {
+                written: 2,
+                duplicates: 2,
+                conflicts: 1
+            }
+        );
+        assert_eq!(files, [file("a.js", "b")]);
+    }
+
+    #[test]
+    fn skip_keeps_existing_files() {
+        let (stats, files) = write_twice(ConflictPolicy::Skip);
+        assert_eq!(
+            stats,
+            WriteStats {
+                written: 1,
+                duplicates: 1,
+                conflicts: 2
+            }
+        );
+        assert_eq!(files, [file("a.js", "a")]);
+    }
+
+    #[test]
+    fn version_writes_side_by_side_once() {
+        let (stats, files) = write_twice(ConflictPolicy::Version);
+        assert_eq!(
+            stats,
+            WriteStats {
+                written: 2,
+                duplicates: 2,
+                conflicts: 1
+            }
+        );
+        assert_eq!(files, [file("a.js", "a"), file("a~3e23e816.js", "b")]);
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!("skip".parse(), Ok(ConflictPolicy::Skip));
+        assert!("replace".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn unsafe_and_reserved_names() {
+        let mut writer = SourceWriter::new(Box::new(MemorySink::new()), ConflictPolicy::Version);
+        assert_eq!(
+            writer.write("manifest.json", "{}").unwrap(),
+            WriteOutcome::Written(PathBuf::from("manifest.json_"))
+        );
+        assert!(matches!(
+            writer.write("../..", "x"),
+            Err(ParsesmError::PathSafety { .. })
+        ));
+        assert!(writer.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn clamped_paths_are_warned_about() {
+        let mut writer = SourceWriter::new(Box::new(MemorySink::new()), ConflictPolicy::Version);
+        assert_eq!(
+            writer.write("../../src/a.js", "a").unwrap(),
+            WriteOutcome::Written(PathBuf::from("src/a.js"))
+        );
+        let warnings = writer.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(warnings[0], ParsesmError::PathSafety { .. }));
+        assert!(writer.take_warnings().is_empty());
+    }
+}